@@ -1,7 +1,4 @@
 mergeInto(LibraryManager.library, {
-  start_javascript_play_sound: function(sound_id) {
-	return play_sound(sound_id);
-  },
   start_game: function() {
   	return start_game();
   },
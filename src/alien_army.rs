@@ -1,15 +1,96 @@
-use crate::gl_helper::model::Model;
-use crate::game::{MovingAlien, ROW_SIZE, SCALE, Update, GROUND, GRID_WIDTH, Render, ALIEN_RADIUS};
+use std::rc::Rc;
+
+use crate::gl_helper::model::{Model, Normals};
+use crate::gl_helper::assets::Assets;
+use crate::gl_helper::instance_model::ModelInstance;
+use crate::game::{MovingAlien, ROW_SIZE, SCALE, Update, GROUND, GRID_WIDTH};
 use cgmath::{Vector3, Matrix4, Deg};
 use rand::Rng;
 use crate::{gl};
+use crate::arena::ARENA_HALF_SIZE;
+use crate::gl_helper::shadow::ShadowMap;
+use crate::gl_helper::shader::ShaderProgram;
 
 pub const SCALE_GRID: f32 = 0.030;
 const TOUCH_GROUND: f32 = 0.02;
 const MOVE_DOWN_TARGET: f32 = 0.12;
+/// Size the alien model is drawn at
+const ALIEN_SCALE: f32 = 0.002;
+pub const ALIEN_MODEL: &str = "resources/models/anotheralien.gltf";
+/// Height a new army starts marching at
+const START_HEIGHT: f32 = 0.3;
+/// delta is about a frame's milliseconds divided by 128, animations are timed in seconds
+pub const SECONDS_PER_DELTA: f32 = 0.128;
+
+/// What an alien's model is acting out
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AlienAnimation {
+    March,
+    Dive,
+    /// Knocked by another alien, plays once then goes back to marching or diving
+    Hit,
+    /// Shot, plays once and holds its last frame
+    Death,
+}
+
+impl AlienAnimation {
+    fn name(&self) -> &'static str {
+        match self {
+            AlienAnimation::March => "march",
+            AlienAnimation::Dive => "dive",
+            AlienAnimation::Hit => "hit",
+            AlienAnimation::Death => "death",
+        }
+    }
+
+    pub fn loops(&self) -> bool {
+        match self {
+            AlienAnimation::March | AlienAnimation::Dive => true,
+            AlienAnimation::Hit | AlienAnimation::Death => false,
+        }
+    }
+}
+
+/// Where each AlienAnimation is in the alien model, None if the model does not have it
+#[derive(Clone, Copy)]
+pub struct AlienAnimations {
+    march: Option<usize>,
+    dive: Option<usize>,
+    hit: Option<usize>,
+    death: Option<usize>,
+}
+
+impl AlienAnimations {
+    fn find(model: &Model) -> AlienAnimations {
+        let animations = AlienAnimations {
+            march: model.animation(AlienAnimation::March.name()),
+            dive: model.animation(AlienAnimation::Dive.name()),
+            hit: model.animation(AlienAnimation::Hit.name()),
+            death: model.animation(AlienAnimation::Death.name()),
+        };
+        for animation in &[AlienAnimation::March, AlienAnimation::Dive, AlienAnimation::Hit, AlienAnimation::Death] {
+            if animations.index(*animation).is_none() {
+                println!("alien model has no {} animation", animation.name());
+            }
+        }
+        animations
+    }
+
+    pub fn index(&self, animation: AlienAnimation) -> Option<usize> {
+        match animation {
+            AlienAnimation::March => self.march,
+            AlienAnimation::Dive => self.dive,
+            AlienAnimation::Hit => self.hit,
+            AlienAnimation::Death => self.death,
+        }
+    }
+}
 
 pub struct AlienArmy {
-    model: Model,
+    model: Rc<Model>,
+    animations: AlienAnimations,
+    /// Collision radius, the model's bounding sphere at the size it is drawn
+    pub alien_radius: f32,
     moving_down: i32,
     pub all_aliens: Vec<MovingAlien>,
     add_x: f32,
@@ -18,26 +99,31 @@ pub struct AlienArmy {
     march_down: f32,
     all_move_down: bool,
     lap: i32,
+    pub landings: u32,
 }
 
 impl AlienArmy {
-    pub fn new(gl: &gl::Gl) -> AlienArmy {
-        let model = Model::new(gl, "resources/models/anotheralien.obj","resources/models/anotheralien.png");
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> AlienArmy {
+        let model = assets.model(gl, ALIEN_MODEL, Normals::Smooth)
+            .unwrap_or_else(|e| panic!("unable to load the alien model {}", e));
         AlienArmy {
+            animations: AlienAnimations::find(&model),
+            alien_radius: model.bounding_radius * ALIEN_SCALE,
             model,
             moving_down: 0,
             all_aliens: Vec::new(),
             add_x: 1.0,
             add_z: 0.0,
             difficult: 0.03,
-            march_down: 0.3,
+            march_down: START_HEIGHT,
             all_move_down: false,
             lap: 0,
+            landings: 0,
         }
     }
     fn create_new_army(&mut self) {
         //self.all_aliens.clear();
-        self.march_down = 0.3;
+        self.march_down = START_HEIGHT;
         for i in (0..self.all_aliens.len()).rev() {
             if self.all_aliens.get(i).unwrap().landed == false  {
                 self.all_aliens.remove(i);
@@ -49,7 +135,7 @@ impl AlienArmy {
                 let z: f32 = col as f32 - ROW_SIZE as f32 / 2.0;
                 let y = self.march_down;
                 let position = Vector3::new(x as f32 * SCALE, y, z as f32 * SCALE);
-                let alien = MovingAlien::new(&self.model, position, ALIEN_RADIUS, 0.002);
+                let alien = MovingAlien::new(&self.model, self.animations, position, self.alien_radius, ALIEN_SCALE);
                 self.all_aliens.push(alien);
             }
         }
@@ -66,11 +152,15 @@ impl AlienArmy {
         for alien in &mut self.all_aliens {
             alien.update(delta);
             if alien.drop_down == true && alien.movement_collision.been_hit == false && alien.landed == false {
-                AlienArmy::move_it_down(down, alien, true)
+                if AlienArmy::move_it_down(down, alien, true) {
+                    self.landings = self.landings + 1;
+                }
             }
             if self.all_move_down {
                 if alien.drop_down == false && alien.landed == false {
-                    AlienArmy::move_it_down(down, alien, false);
+                    if AlienArmy::move_it_down(down, alien, false) {
+                        self.landings = self.landings + 1;
+                    }
                     last_y = alien.movement_collision.position.y;
                 }
             } else {
@@ -88,19 +178,24 @@ impl AlienArmy {
                 }
             }
         }
-        if self.add_z == 0.0 && max_x > GRID_WIDTH as f32 * SCALE_GRID {
+        // how far the army reaches each way, the outermost marching alien's centre plus its radius
+        let (right, left) = (max_x + self.alien_radius, -min_x + self.alien_radius);
+        let (front, back) = (max_z + self.alien_radius, -min_z + self.alien_radius);
+        // it turns once it has marched the width of the grid, or sooner if it would go through an arena wall
+        let limit = (GRID_WIDTH as f32 * SCALE_GRID + self.alien_radius).min(ARENA_HALF_SIZE);
+        if self.add_z == 0.0 && right > limit {
             self.add_x = 0.0;
             self.add_z = -1.0;
             self.lap = self.lap + 1;
-        } else if self.add_x != -1.0 && min_z < -GRID_WIDTH as f32 * SCALE_GRID {
+        } else if self.add_x != -1.0 && back > limit {
             self.add_x = -1.0;
             self.add_z = 0.0;
             self.lap = self.lap + 1;
-        } else if self.add_z != 1.0 && min_x < -GRID_WIDTH as f32 * SCALE_GRID {
+        } else if self.add_z != 1.0 && left > limit {
             self.add_x = 0.0;
             self.add_z = 1.0;
             self.lap = self.lap + 1;
-        } else if self.add_x != 1.0 && max_z > GRID_WIDTH as f32 * SCALE_GRID {
+        } else if self.add_x != 1.0 && front > limit {
             self.add_x = 1.0;
             self.add_z = 0.0;
             self.lap = self.lap + 1;
@@ -117,6 +212,21 @@ impl AlienArmy {
         }
     }
 
+    /// Shader program the aliens are drawn with, for setting the lighting
+    pub fn shader(&self) -> &ShaderProgram {
+        &self.model.our_shader
+    }
+
+    /// How far the army has marched down, 0 when a wave starts and 1 once it reaches the ground
+    pub fn descent(&self) -> f32 {
+        ((START_HEIGHT - self.march_down) / START_HEIGHT).max(0.0).min(1.0)
+    }
+
+    /// Aliens still in the air
+    pub fn remaining(&self) -> usize {
+        self.all_aliens.iter().filter(|a| !a.landed).count()
+    }
+
     pub fn create_new_army_if_needed(&mut self) -> (bool, usize) {
 
         let landed = self.all_aliens.iter().filter(|a|a.landed).count();
@@ -128,7 +238,8 @@ impl AlienArmy {
         return (false,0);
     }
 
-    fn move_it_down(down: f32, alien: &mut MovingAlien,spin:bool) {
+    /// returns true if the alien touched the ground on this move
+    fn move_it_down(down: f32, alien: &mut MovingAlien,spin:bool) -> bool {
         alien.movement_collision.position.y = alien.movement_collision.position.y - down;
         alien.spin = alien.spin + 1.0;
         if spin {
@@ -139,7 +250,9 @@ impl AlienArmy {
         }
         if alien.movement_collision.position.y - TOUCH_GROUND < GROUND {
             alien.landed = true;
+            return true;
         }
+        false
     }
 
     fn move_down_picker(&mut self, delta: f32) -> f32 {
@@ -172,9 +285,15 @@ impl AlienArmy {
         }
         down
     }
-    pub(crate) fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
-        for alien in &mut self.all_aliens {
-            alien.model_instance.render(gl, &view, &projection);
-        }
+    fn model_instances(&self) -> Vec<&ModelInstance> {
+        self.all_aliens.iter().map(|alien| &alien.model_instance).collect()
+    }
+
+    pub(crate) fn render_shadow(&self, gl: &gl::Gl, shadow: &ShadowMap) {
+        ModelInstance::render_shadows(gl, &self.model_instances(), shadow);
+    }
+
+    pub(crate) fn render(&mut self, gl: &gl::Gl) {
+        ModelInstance::render_all(gl, &self.model_instances());
     }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use cgmath::{vec3, Vector3, Matrix4, InnerSpace};
+
+use crate::cube::Cube;
+use crate::game::{Render, GROUND};
+use crate::gl;
+use crate::gl_helper::assets::Assets;
+
+/// Half the width of the playfield, the ground is drawn to the same size
+pub const ARENA_HALF_SIZE: f32 = 2.0;
+pub const ARENA_HEIGHT: f32 = 1.0;
+const WALL_THICKNESS: f32 = 0.001;
+
+pub fn inside_arena(position: Vector3<f32>) -> bool {
+    position.x.abs() <= ARENA_HALF_SIZE && position.z.abs() <= ARENA_HALF_SIZE &&
+        position.y >= GROUND && position.y <= ARENA_HEIGHT
+}
+
+/// Keeps a moving object inside the walls. Only the part of the velocity heading into a wall
+/// is removed so the object slides along it rather than stopping dead.
+pub fn keep_in_arena(position: &mut Vector3<f32>, velocity: &mut Vector3<f32>, radius: f32) {
+    let limit = ARENA_HALF_SIZE - radius;
+    if position.x > limit {
+        position.x = limit;
+        velocity.x = velocity.x.min(0.0);
+    } else if position.x < -limit {
+        position.x = -limit;
+        velocity.x = velocity.x.max(0.0);
+    }
+    if position.z > limit {
+        position.z = limit;
+        velocity.z = velocity.z.min(0.0);
+    } else if position.z < -limit {
+        position.z = -limit;
+        velocity.z = velocity.z.max(0.0);
+    }
+}
+
+/// Pushes a moving object out of a sphere so it slides around the surface of it
+pub fn slide_off_sphere(position: &mut Vector3<f32>, velocity: &mut Vector3<f32>, centre: Vector3<f32>, radius: f32) {
+    let away = *position - centre;
+    let distance = away.magnitude();
+    if distance >= radius || distance == 0.0 {
+        return;
+    }
+    let normal = away / distance;
+    *position = centre + normal * radius;
+    let into = velocity.dot(normal);
+    if into < 0.0 {
+        *velocity -= normal * into;
+    }
+}
+
+/// Translucent walls around the edge of the playfield
+pub struct ForceField {
+    x_wall: Cube,
+    z_wall: Cube,
+}
+
+impl ForceField {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> ForceField {
+        let half_height = ARENA_HEIGHT / 2.0;
+        ForceField {
+            x_wall: Cube::new(gl, assets, "resources/forcefield.png", vec3(WALL_THICKNESS, half_height, ARENA_HALF_SIZE), 8.0),
+            z_wall: Cube::new(gl, assets, "resources/forcefield.png", vec3(ARENA_HALF_SIZE, half_height, WALL_THICKNESS), 8.0),
+        }
+    }
+}
+
+impl Render for ForceField {
+    fn render(&mut self, gl: &gl::Gl) {
+        let y = GROUND + ARENA_HEIGHT / 2.0;
+        unsafe {
+            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl.DepthMask(gl::FALSE);
+        }
+        for side in [-1.0f32, 1.0].iter() {
+            let matrix = Matrix4::<f32>::from_translation(vec3(side * ARENA_HALF_SIZE, y, 0.0));
+            self.x_wall.render(gl, &matrix);
+            let matrix = Matrix4::<f32>::from_translation(vec3(0.0, y, side * ARENA_HALF_SIZE));
+            self.z_wall.render(gl, &matrix);
+        }
+        unsafe {
+            gl.DepthMask(gl::TRUE);
+            gl.BlendFunc(gl::ONE, gl::ZERO);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cgmath::{vec3, InnerSpace, Vector3};
+use sdl2::{AudioSubsystem, Sdl};
+use sdl2::mixer::{self, Channel, Chunk, Group, LoaderRWops, DEFAULT_FORMAT, MAX_VOLUME};
+use sdl2::rwops::RWops;
+
+use crate::flying_camera::Flying_Camera;
+use crate::settings::Settings;
+use crate::sfxr::{SfxParams, write_wav, wav_bytes};
+
+const FREQUENCY: i32 = 44_100;
+const CHUNK_SIZE: i32 = 1_024;
+const EFFECT_CHANNELS: i32 = 12;
+/// Looping sounds such as the dive whines have channels of their own, so a one-shot effect can
+/// never take one over while its owner still thinks it is playing there
+pub const LOOP_CHANNELS: i32 = 4;
+const INTERFACE_CHANNELS: i32 = 4;
+/// One channel for each layer of the adaptive music, see music.rs
+pub const MUSIC_LAYERS: i32 = 3;
+const FIRST_LOOP_CHANNEL: i32 = EFFECT_CHANNELS;
+const FIRST_INTERFACE_CHANNEL: i32 = FIRST_LOOP_CHANNEL + LOOP_CHANNELS;
+const FIRST_MUSIC_CHANNEL: i32 = FIRST_INTERFACE_CHANNEL + INTERFACE_CHANNELS;
+/// Mixer groups, one for each category and one for the loop channels
+const EFFECT_GROUP: Group = Group(1);
+const INTERFACE_GROUP: Group = Group(2);
+const LOOP_GROUP: Group = Group(3);
+const MUSIC_GROUP: Group = Group(4);
+/// Sounds further away than this can not be heard, the arena is 4 across
+const HEARING_DISTANCE: f32 = 5.0;
+
+/// Each category has its own volume and its own channels, so lots of explosions can not
+/// stop a menu click from being heard
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum SoundCategory {
+    Effects,
+    Interface,
+    Music,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Sound {
+    Hit,
+    Shot,
+    Dive,
+    PowerUp,
+    Select,
+}
+
+const SOUNDS_DIRECTORY: &str = "resources/sounds";
+
+/// Every sound is made when the game starts from the sfxr parameters in its file
+pub const SOUND_EFFECTS: [(Sound, &str, SoundCategory); 5] = [
+    (Sound::Hit, "resources/sounds/explosion.sfx", SoundCategory::Effects),
+    (Sound::Shot, "resources/sounds/laser.sfx", SoundCategory::Effects),
+    (Sound::Dive, "resources/sounds/dive.sfx", SoundCategory::Effects),
+    (Sound::PowerUp, "resources/sounds/powerup.sfx", SoundCategory::Effects),
+    (Sound::Select, "resources/sounds/select.sfx", SoundCategory::Interface),
+];
+
+/// Writes every sfxr file in SOUNDS_DIRECTORY, music layers included, as a WAV file into a
+/// directory, so they can be listened to or used somewhere else
+pub fn export_sounds(directory: &str) -> Result<(), String> {
+    fs::create_dir_all(directory).map_err(|e| format!("{} {}", directory, e))?;
+    let mut files: Vec<PathBuf> = fs::read_dir(SOUNDS_DIRECTORY)
+        .map_err(|e| format!("{} {}", SOUNDS_DIRECTORY, e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "sfx"))
+        .collect();
+    files.sort();
+    for filename in files {
+        let name = filename.file_stem().unwrap().to_string_lossy();
+        let wav = Path::new(directory).join(format!("{}.wav", name));
+        write_wav(&wav.to_string_lossy(), &SfxParams::from_file(&filename.to_string_lossy()).synthesize())?;
+        println!("wrote {}", wav.display());
+    }
+    Ok(())
+}
+
+/// Where the sounds are heard from, taken from the player camera each frame
+#[derive(Clone, Copy)]
+pub struct Listener {
+    pub position: Vector3<f32>,
+    pub front: Vector3<f32>,
+    pub right: Vector3<f32>,
+}
+
+impl Listener {
+    pub fn from_camera(camera: &Flying_Camera) -> Listener {
+        Listener {
+            position: vec3(camera.Position.x, camera.Position.y, camera.Position.z),
+            front: camera.Front,
+            right: camera.Right,
+        }
+    }
+
+    /// Angle in degrees clockwise from straight ahead, 90 is to the right, and distance
+    /// from 0 to 255 as SDL mixer wants them
+    pub fn locate(&self, position: Vector3<f32>) -> (i16, u8) {
+        let offset = position - self.position;
+        let distance = offset.magnitude();
+        let angle = offset.dot(self.right).atan2(offset.dot(self.front)).to_degrees();
+        let angle = if angle < 0.0 { angle + 360.0 } else { angle };
+        let loudness = (distance / HEARING_DISTANCE).min(1.0);
+        (angle.round() as i16 % 360, (loudness * 255.0) as u8)
+    }
+}
+
+pub struct AudioSettings {
+    /// SDL audio driver to use, "dummy" plays nothing and needs no sound card
+    pub driver: Option<String>,
+    pub master: f32,
+    pub effects: f32,
+    pub interface: f32,
+    pub music: f32,
+}
+
+impl AudioSettings {
+    pub fn from_settings(settings: &Settings) -> AudioSettings {
+        AudioSettings {
+            driver: settings.get("audio.driver").map(|d| d.to_string()),
+            master: settings.get_or("audio.master", 1.0),
+            effects: settings.get_or("audio.effects", 1.0),
+            interface: settings.get_or("audio.interface", 1.0),
+            music: settings.get_or("audio.music", 0.6),
+        }
+    }
+
+    pub fn volume(&self, category: SoundCategory) -> f32 {
+        let volume = match category {
+            SoundCategory::Effects => self.effects,
+            SoundCategory::Interface => self.interface,
+            SoundCategory::Music => self.music,
+        };
+        (volume * self.master).max(0.0).min(1.0)
+    }
+}
+
+/// Sound effects and music through SDL2 mixer, the same on native and in the browser.
+/// If audio can not be opened the game carries on silently.
+pub struct Audio {
+    pub settings: AudioSettings,
+    _audio: Option<AudioSubsystem>,
+    sounds: HashMap<Sound, (Chunk, SoundCategory)>,
+    effects_paused: bool,
+}
+
+impl Audio {
+    pub fn new(sdl: &Sdl, settings: AudioSettings) -> Audio {
+        let mut audio = Audio {
+            settings,
+            _audio: None,
+            sounds: HashMap::new(),
+            effects_paused: false,
+        };
+        if let Some(driver) = &audio.settings.driver {
+            sdl2::hint::set("SDL_AUDIODRIVER", driver);
+        }
+        match Audio::open(sdl) {
+            Ok(subsystem) => audio._audio = Some(subsystem),
+            Err(e) => println!("no audio {}", e),
+        }
+        audio
+    }
+
+    /// WAV support is built in to SDL mixer so there is no need for mixer::init
+    fn open(sdl: &Sdl) -> Result<AudioSubsystem, String> {
+        let subsystem = sdl.audio()?;
+        mixer::open_audio(FREQUENCY, DEFAULT_FORMAT, 2, CHUNK_SIZE)?;
+        mixer::allocate_channels(FIRST_MUSIC_CHANNEL + MUSIC_LAYERS);
+        EFFECT_GROUP.add_channels_range(0, EFFECT_CHANNELS - 1);
+        LOOP_GROUP.add_channels_range(FIRST_LOOP_CHANNEL, FIRST_INTERFACE_CHANNEL - 1);
+        INTERFACE_GROUP.add_channels_range(FIRST_INTERFACE_CHANNEL, FIRST_MUSIC_CHANNEL - 1);
+        MUSIC_GROUP.add_channels_range(FIRST_MUSIC_CHANNEL, FIRST_MUSIC_CHANNEL + MUSIC_LAYERS - 1);
+        Ok(subsystem)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self._audio.is_some()
+    }
+
+    fn group(category: SoundCategory) -> Group {
+        match category {
+            SoundCategory::Effects => EFFECT_GROUP,
+            SoundCategory::Interface => INTERFACE_GROUP,
+            SoundCategory::Music => MUSIC_GROUP,
+        }
+    }
+
+    /// Synthesizes a sound from an sfxr parameter file
+    pub fn load_sfx(&mut self, sound: Sound, filename: &str, category: SoundCategory) {
+        if let Some(chunk) = self.synthesize(&SfxParams::from_file(filename), filename) {
+            self.sounds.insert(sound, (chunk, category));
+        }
+    }
+
+    /// Makes a sound that is played by its owner rather than by name, None if there is no audio
+    pub fn synthesize(&self, params: &SfxParams, name: &str) -> Option<Chunk> {
+        if !self.enabled() {
+            return None;
+        }
+        let wav = wav_bytes(&params.synthesize());
+        match RWops::from_bytes(&wav).and_then(|rw| rw.load_wav()) {
+            Ok(chunk) => Some(chunk),
+            Err(e) => {
+                println!("unable to make sound {} {}", name, e);
+                None
+            }
+        }
+    }
+
+    pub fn load_sound_effects(&mut self) {
+        for (sound, filename, category) in SOUND_EFFECTS.iter() {
+            self.load_sfx(*sound, filename, *category);
+        }
+    }
+
+    /// Plays a sound on a free channel of its category, or the oldest one if they are all busy
+    pub fn play(&mut self, sound: Sound) -> Option<Channel> {
+        let group = self.category_group(sound)?;
+        self.start(sound, 0, None, group.find_available().or_else(|| group.find_oldest())?)
+    }
+
+    /// Plays a sound panned and quietened by where it is from the listener
+    pub fn play_at(&mut self, sound: Sound, position: Vector3<f32>, listener: &Listener) -> Option<Channel> {
+        let group = self.category_group(sound)?;
+        self.start(sound, 0, Some(listener.locate(position)), group.find_available().or_else(|| group.find_oldest())?)
+    }
+
+    /// Keeps playing a sound at a position until it is stopped, move it with set_position.
+    /// Loops only ever use the loop channels and never take one that is busy, so there is
+    /// None once LOOP_CHANNELS of them are playing.
+    pub fn loop_at(&mut self, sound: Sound, position: Vector3<f32>, listener: &Listener) -> Option<Channel> {
+        self.start(sound, -1, Some(listener.locate(position)), LOOP_GROUP.find_available()?)
+    }
+
+    fn category_group(&self, sound: Sound) -> Option<Group> {
+        self.sounds.get(&sound).map(|(_, category)| Audio::group(*category))
+    }
+
+    fn start(&mut self, sound: Sound, loops: i32, location: Option<(i16, u8)>, channel: Channel) -> Option<Channel> {
+        let (chunk, category) = self.sounds.get(&sound)?;
+        channel.set_volume((self.settings.volume(*category) * MAX_VOLUME as f32) as i32);
+        // the channel may have been used for a positional sound before
+        let placed = match location {
+            Some((angle, distance)) => channel.set_position(angle, distance),
+            None => channel.unset_position(),
+        };
+        if let Err(e) = placed {
+            println!("unable to position sound {:?} {}", sound, e);
+        }
+        channel.play(chunk, loops).ok()
+    }
+
+    pub fn set_position(&self, channel: Channel, position: Vector3<f32>, listener: &Listener) {
+        let (angle, distance) = listener.locate(position);
+        channel.set_position(angle, distance).ok();
+    }
+
+    pub fn stop(&self, channel: Channel) {
+        channel.halt();
+    }
+
+    /// Starts a note on a music layer's own channel, weight from 0 to 1 is how much of the layer is heard
+    pub fn play_music_layer(&self, layer: i32, chunk: &Chunk, weight: f32) {
+        let channel = Channel(FIRST_MUSIC_CHANNEL + layer);
+        self.set_music_layer_weight(layer, weight);
+        if let Err(e) = channel.play(chunk, 0) {
+            println!("unable to play music layer {} {}", layer, e);
+        }
+    }
+
+    /// Changes the volume of a music layer, even part way through a note
+    pub fn set_music_layer_weight(&self, layer: i32, weight: f32) {
+        if self.enabled() {
+            let volume = self.settings.volume(SoundCategory::Music) * weight.max(0.0).min(1.0);
+            Channel(FIRST_MUSIC_CHANNEL + layer).set_volume((volume * MAX_VOLUME as f32) as i32);
+        }
+    }
+
+    /// Holds the game sounds while paused, interface sounds and music carry on
+    pub fn pause_effects(&mut self, paused: bool) {
+        if !self.enabled() || paused == self.effects_paused {
+            return;
+        }
+        self.effects_paused = paused;
+        for channel in 0..FIRST_INTERFACE_CHANNEL {
+            if paused { Channel(channel).pause() } else { Channel(channel).resume() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs SDL2 and SDL2_mixer installed but no sound card
+    #[test]
+    fn dummy_driver_opens_audio_and_plays() {
+        let sdl = sdl2::init().unwrap();
+        let settings = AudioSettings {
+            driver: Some("dummy".to_string()),
+            master: 1.0,
+            effects: 1.0,
+            interface: 1.0,
+            music: 0.6,
+        };
+        let mut audio = Audio::new(&sdl, settings);
+        assert!(audio.enabled());
+        audio.load_sound_effects();
+        assert_eq!(audio.sounds.len(), SOUND_EFFECTS.len());
+        assert!(audio.play(Sound::Select).is_some());
+    }
+}
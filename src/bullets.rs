@@ -2,8 +2,14 @@ use crate::cube::Cube;
 use cgmath::{vec3, Vector3, Matrix4, };
 use crate::game::{Update, Render, GROUND, MovementAndCollision};
 use crate::{gl, get_next_id};
+use crate::arena::inside_arena;
+use crate::gl_helper::shadow::ShadowMap;
+use crate::gl_helper::instancing::InstanceData;
+use crate::gl_helper::assets::Assets;
 
 const SPEED: f32 = 0.08;//375;
+/// Bullets are about a texel of the shadow map across, their shadows are drawn bigger to show up
+const SHADOW_SCALE: f32 = 4.0;
 
 pub struct Bullets {
     cube: Cube,
@@ -19,8 +25,8 @@ pub struct BulletInstance {
 }
 
 impl Bullets {
-    pub fn new(gl: &gl::Gl) -> Bullets {
-        let cube = Cube::new(&gl, "resources/fire.png", vec3(0.001, 0.001, 0.001), 1.0);
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Bullets {
+        let cube = Cube::new(&gl, assets, "resources/fire.png", vec3(0.001, 0.001, 0.001), 1.0);
         Bullets {
             cube,
             instances: Vec::new(),
@@ -37,6 +43,16 @@ impl Bullets {
         };
         self.instances.push(instance);
     }
+
+    fn instance_data(&self, scale: f32) -> Vec<InstanceData> {
+        self.instances.iter().map(|i| {
+            InstanceData::new(Matrix4::<f32>::from_translation(i.collision.position) * Matrix4::from_scale(scale))
+        }).collect()
+    }
+
+    pub fn render_shadow(&self, gl: &gl::Gl, shadow: &ShadowMap) {
+        self.cube.render_shadows(gl, &self.instance_data(SHADOW_SCALE), shadow);
+    }
 }
 
 impl Update for Bullets {
@@ -50,7 +66,7 @@ impl Update for Bullets {
                 change.collision.been_hit = true;
             }
             change.ticks = change.ticks -1;
-            if change.ticks <= 0  {
+            if change.ticks <= 0 || !inside_arena(change.collision.position) {
                 self.instances.remove(i);
             }
             //change.matrix = Matrix4::<f32>::from_translation(change.collision.position);
@@ -59,11 +75,8 @@ impl Update for Bullets {
 }
 
 impl Render for Bullets {
-    fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
-        for i in &self.instances {
-            let matrix = Matrix4::<f32>::from_translation(i.collision.position);
-
-            self.cube.render(gl, &matrix, view, projection);
-        }
+    fn render(&mut self, gl: &gl::Gl) {
+        let instances = self.instance_data(1.0);
+        self.cube.render_all(gl, &instances);
     }
 }
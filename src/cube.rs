@@ -3,31 +3,38 @@ extern crate cgmath;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
+use std::rc::Rc;
 
 use cgmath::*;
 
-use crate::gl_helper::shader::create_shader;
-use crate::gl_helper::texture::{create_texture_jpg, create_texture_png};
-use crate::gl_helper::gl_matrix4;
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::assets::Assets;
+use crate::gl_helper::shadow::ShadowMap;
+use crate::gl_helper::instancing::{add_instance_attributes, create_instance_buffer, draw_arrays_instanced, upload_instances, InstanceData};
 use crate::{gl};
 
 const IMAGE_VERTEX_SHADER_SOURCE: &str = "#version 300 es
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec2 aTexCoord;
+layout (location = 3) in mat4 aTransform;
+layout (location = 7) in vec4 aTint;
 
 out vec2 TexCoord;
 out vec3 Pos;
+out vec4 Tint;
 
-uniform mat4 transform;
-uniform mat4 projection;
-uniform mat4 view;
+layout (std140) uniform Camera {
+	mat4 view;
+	mat4 projection;
+};
 
 void main()
 {
 
-	gl_Position = projection * view * transform * vec4(aPos ,1.0) ;
+	gl_Position = projection * view * aTransform * vec4(aPos ,1.0) ;
 	TexCoord = vec2(aTexCoord.x, aTexCoord.y);
 	Pos = vec3(aPos);
+	Tint = aTint;
 }
 ";
 
@@ -37,16 +44,80 @@ out vec4 FragColor;
 
 in vec2 TexCoord;
 in vec3 Pos;
+in vec4 Tint;
 
 uniform sampler2D theTexture;
 
 void main()
 {
-	FragColor = texture(theTexture, TexCoord);
+	FragColor = texture(theTexture, TexCoord) * Tint;
 
 }
 ";
 
+const SHADOWED_VERTEX_SHADER_SOURCE: &str = "#version 300 es
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec2 aTexCoord;
+layout (location = 3) in mat4 aTransform;
+layout (location = 7) in vec4 aTint;
+
+out vec2 TexCoord;
+out vec4 LightSpacePos;
+out vec4 Tint;
+
+layout (std140) uniform Camera {
+	mat4 view;
+	mat4 projection;
+};
+uniform mat4 lightSpace;
+
+void main()
+{
+	vec4 worldPos = aTransform * vec4(aPos, 1.0);
+	gl_Position = projection * view * worldPos;
+	TexCoord = vec2(aTexCoord.x, aTexCoord.y);
+	LightSpacePos = lightSpace * worldPos;
+	Tint = aTint;
+}
+";
+
+const SHADOWED_FRAGMENT_SHADER_SOURCE: &str = "#version 300 es
+precision mediump float;
+out vec4 FragColor;
+
+in vec2 TexCoord;
+in vec4 LightSpacePos;
+in vec4 Tint;
+
+uniform sampler2D theTexture;
+uniform mediump sampler2DShadow shadowMap;
+uniform int shadowsEnabled;
+
+// how much light gets through, 0 in full shadow and 1 in the open
+float sunlight()
+{
+	vec3 p = LightSpacePos.xyz / LightSpacePos.w * 0.5 + 0.5;
+	if (shadowsEnabled == 0 || p.z > 1.0) {
+		return 1.0;
+	}
+	// 3x3 percentage closer filter, the bias stops the surface shadowing itself
+	vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++) {
+		for (int y = -1; y <= 1; y++) {
+			lit += texture(shadowMap, vec3(p.xy + vec2(float(x), float(y)) * texel, p.z - 0.001));
+		}
+	}
+	return lit / 9.0;
+}
+
+void main()
+{
+	vec4 colour = texture(theTexture, TexCoord);
+	FragColor = vec4(colour.rgb * (0.4 + 0.6 * sunlight()), colour.a) * Tint;
+}
+";
+
 
 pub struct CubeInstance {
     pub id: u128,
@@ -60,15 +131,33 @@ impl PartialEq for CubeInstance {
 }
 
 pub struct Cube {
-    our_shader: u32,
+    our_shader: Rc<ShaderProgram>,
     texture: u32,
     vao: u32,
+    /// Per instance transforms and tints
+    instance_buffer: u32,
+    /// Drawn with the shader that darkens it where the shadow map is in front
+    receives_shadows: bool,
 }
 
 impl Cube {
-    pub fn new(gl: &gl::Gl, image_file: &str, size: Vector3<f32>, texture_end: f32) -> Cube {
-        let (our_shader, _vbo, vao, texture) = unsafe {
-            let our_shader = create_shader(&gl, IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE);
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, image_file: &str, size: Vector3<f32>, texture_end: f32) -> Cube {
+        Cube::create(gl, assets, image_file, size, texture_end, false)
+    }
+
+    /// A cube that shadows fall on, draw it with render_shadowed
+    pub fn new_shadowed(gl: &gl::Gl, assets: &mut Assets, image_file: &str, size: Vector3<f32>, texture_end: f32) -> Cube {
+        Cube::create(gl, assets, image_file, size, texture_end, true)
+    }
+
+    fn create(gl: &gl::Gl, assets: &mut Assets, image_file: &str, size: Vector3<f32>, texture_end: f32, receives_shadows: bool) -> Cube {
+        let our_shader = if receives_shadows {
+            assets.shader(gl, "cube shadowed", SHADOWED_VERTEX_SHADER_SOURCE, SHADOWED_FRAGMENT_SHADER_SOURCE)
+        } else {
+            assets.shader(gl, "cube", IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE)
+        };
+        let texture = assets.texture(gl, image_file).unwrap();
+        let (_vbo, vao) = unsafe {
 
             let vertices: [f32; 180] = [
 // positions       // texture coords
@@ -127,38 +216,56 @@ impl Cube {
             gl.VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, (3 * mem::size_of::<gl::types::GLfloat>()) as *const c_void);
             gl.EnableVertexAttribArray(1);
 
-            let texture = if image_file.ends_with(".png") {
-                create_texture_png(&gl, image_file)
-            } else {
-                create_texture_jpg(&gl, image_file)
-            };
-
-
-            (our_shader, vbo, vao, texture)
+            (vbo, vao)
         };
+        let instance_buffer = create_instance_buffer(gl);
+        add_instance_attributes(gl, vao, instance_buffer);
 
         Cube {
             our_shader: our_shader,
             texture: texture,
             vao,
+            instance_buffer,
+            receives_shadows,
         }
     }
 
 
-    pub fn render(&mut self, gl: &gl::Gl, matrix: &Matrix4<f32>, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+    pub fn render(&mut self, gl: &gl::Gl, matrix: &Matrix4<f32>) {
+        self.render_all(gl, &[InstanceData::new(*matrix)]);
+    }
+
+    /// Draws a copy of the cube for each instance in one draw call
+    pub fn render_all(&mut self, gl: &gl::Gl, instances: &[InstanceData]) {
+        upload_instances(gl, self.instance_buffer, instances);
         unsafe {
             gl.ActiveTexture(gl::TEXTURE0);
             gl.BindTexture(gl::TEXTURE_2D, self.texture);
-            gl.UseProgram(self.our_shader);
-            gl.BindVertexArray(self.vao);
-
+        }
+        self.our_shader.use_program(gl);
+        draw_arrays_instanced(gl, self.vao, 36, instances.len());
+    }
 
-            //self.transform = self.transform * self.rotate * self.forward;
-            gl_matrix4(gl, self.our_shader, *matrix, "transform");
-            gl_matrix4(gl, self.our_shader, *view, "view");
-            gl_matrix4(gl, self.our_shader, *projection, "projection");
-            gl.DrawArrays(gl::TRIANGLES, 0, 36);
+    /// Like render, with shadows from the shadow map if there is one
+    pub fn render_shadowed(&mut self, gl: &gl::Gl, matrix: &Matrix4<f32>, shadow: Option<&ShadowMap>) {
+        if self.receives_shadows {
+            self.our_shader.use_program(gl);
+            self.our_shader.set_int(gl, "shadowsEnabled", shadow.is_some() as i32);
+            self.our_shader.set_int(gl, "shadowMap", 1);
+            if let Some(shadow) = shadow {
+                self.our_shader.set_matrix4(gl, "lightSpace", &shadow.light_space);
+                unsafe {
+                    gl.ActiveTexture(gl::TEXTURE1);
+                    gl.BindTexture(gl::TEXTURE_2D, shadow.depth_texture);
+                }
+            }
         }
+        self.render(gl, matrix);
     }
-}
 
+    /// Draws a copy of the cube for each instance into the shadow map
+    pub fn render_shadows(&self, gl: &gl::Gl, instances: &[InstanceData], shadow: &ShadowMap) {
+        upload_instances(gl, self.instance_buffer, instances);
+        shadow.cast_arrays(gl, self.vao, 36, instances.len());
+    }
+}
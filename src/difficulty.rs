@@ -0,0 +1,40 @@
+use std::str::FromStr;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl FromStr for Difficulty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Difficulty {
+    /// Top speed of the player, the harder it is the slower you are to get out of the way
+    pub fn player_max_speed(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.16,
+            Difficulty::Normal => 0.125,
+            Difficulty::Hard => 0.1,
+        }
+    }
+
+    pub fn player_acceleration(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.05,
+            Difficulty::Normal => 0.035,
+            Difficulty::Hard => 0.025,
+        }
+    }
+}
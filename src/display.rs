@@ -0,0 +1,126 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use sdl2::VideoSubsystem;
+use sdl2::video::{FullscreenType, SwapInterval, Window};
+
+use crate::settings::Settings;
+use crate::{WIDTH, HEIGHT};
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl FromStr for WindowMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "windowed" => Ok(WindowMode::Windowed),
+            "borderless" => Ok(WindowMode::Borderless),
+            "fullscreen" => Ok(WindowMode::Fullscreen),
+            _ => Err(()),
+        }
+    }
+}
+
+impl WindowMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowMode::Windowed => "windowed",
+            WindowMode::Borderless => "borderless",
+            WindowMode::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+pub struct DisplaySettings {
+    pub mode: WindowMode,
+    pub width: u32,
+    pub height: u32,
+    pub vsync: bool,
+    /// Most frames drawn a second, 0 for no limit
+    pub frame_cap: u32,
+}
+
+impl DisplaySettings {
+    pub fn from_settings(settings: &Settings) -> DisplaySettings {
+        DisplaySettings {
+            mode: settings.get_or("display.mode", WindowMode::Windowed),
+            width: settings.get_or("display.width", WIDTH),
+            height: settings.get_or("display.height", HEIGHT),
+            vsync: settings.get_or("display.vsync", true),
+            frame_cap: settings.get_or("display.frame_cap", 0),
+        }
+    }
+
+    pub fn write_to(&self, settings: &mut Settings) {
+        settings.set("display.mode", self.mode.name().to_string());
+        settings.set("display.width", self.width.to_string());
+        settings.set("display.height", self.height.to_string());
+        settings.set("display.vsync", self.vsync.to_string());
+        settings.set("display.frame_cap", self.frame_cap.to_string());
+    }
+
+    /// Windowed, borderless then fullscreen and round again
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless => WindowMode::Fullscreen,
+            WindowMode::Fullscreen => WindowMode::Windowed,
+        };
+    }
+
+    pub fn apply_window_mode(&self, window: &mut Window) {
+        // borderless is a window the size of the desktop, fullscreen changes the display mode
+        let fullscreen = match self.mode {
+            WindowMode::Windowed => FullscreenType::Off,
+            WindowMode::Borderless => FullscreenType::Desktop,
+            WindowMode::Fullscreen => FullscreenType::True,
+        };
+        if let Err(e) = window.set_fullscreen(fullscreen) {
+            println!("unable to change to {} {}", self.mode.name(), e);
+        }
+        // leaving fullscreen can keep the size of the display it was on
+        if self.mode == WindowMode::Windowed {
+            if let Err(e) = window.set_size(self.width, self.height) {
+                println!("unable to size the window {}x{} {}", self.width, self.height, e);
+            }
+        }
+    }
+
+    pub fn apply_vsync(&self, video: &VideoSubsystem) {
+        let interval = if self.vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+        if let Err(e) = video.gl_set_swap_interval(interval) {
+            println!("unable to set vsync {} {}", self.vsync, e);
+        }
+    }
+}
+
+/// Sleeps out the rest of a frame when there is a frame rate cap. Not used in the browser,
+/// where requestAnimationFrame already paces the main loop.
+pub struct FrameLimiter {
+    last_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> FrameLimiter {
+        FrameLimiter {
+            last_frame: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self, frame_cap: u32) {
+        if frame_cap > 0 {
+            let frame_time = Duration::from_secs(1) / frame_cap;
+            let elapsed = self.last_frame.elapsed();
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+        self.last_frame = Instant::now();
+    }
+}
@@ -3,6 +3,8 @@ use cgmath::{vec3, Vector3, Matrix4};
 use crate::game::{Update, Render, };
 use crate::{gl, };
 use rand::Rng;
+use crate::gl_helper::instancing::InstanceData;
+use crate::gl_helper::assets::Assets;
 
 
 pub struct Explosions {
@@ -19,8 +21,8 @@ pub struct ExplosionInstance {
 }
 
 impl Explosions {
-    pub fn new(gl: &gl::Gl) -> Explosions {
-        let cube = Cube::new(&gl, "resources/fire.png", vec3(0.005, 0.005, 0.005), 1.0);
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Explosions {
+        let cube = Cube::new(&gl, assets, "resources/fire.png", vec3(0.005, 0.005, 0.005), 1.0);
         Explosions {
             cube,
             instances: Vec::new(),
@@ -63,11 +65,10 @@ impl Update for Explosions {
 }
 
 impl Render for Explosions {
-    fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
-        for i in &self.instances {
-            let matrix = Matrix4::<f32>::from_translation(i.position);
-
-            self.cube.render(gl, &matrix, view, projection);
-        }
+    fn render(&mut self, gl: &gl::Gl) {
+        let instances: Vec<InstanceData> = self.instances.iter()
+            .map(|i| InstanceData::new(Matrix4::<f32>::from_translation(i.position)))
+            .collect();
+        self.cube.render_all(gl, &instances);
     }
 }
@@ -106,9 +106,17 @@ impl Flying_Camera {
         }
     }
 
+    /// Processes relative mouse movement in pixels, moving the mouse up looks up unless invert_y is set
+    pub fn processMouseMovement(&mut self, xoffset: f32, yoffset: f32, sensitivity: f32, invert_y: bool) {
+        let yoffset = if invert_y { yoffset } else { -yoffset };
+        self.Yaw = self.Yaw + xoffset * sensitivity;
+        self.Pitch = (self.Pitch + yoffset * sensitivity).max(0.0).min(80.0);
+        self.updateFlying_CameraVectors();
+    }
+
 
     /// Calculates the front vector from the Flying_Camera's (updated) Eular Angles
-    fn updateFlying_CameraVectors(&mut self) {
+    pub fn updateFlying_CameraVectors(&mut self) {
         // Calculate the new Front vector
         let front = Vector3 {
             x: self.Yaw.to_radians().cos() * self.Pitch.to_radians().cos() ,
@@ -3,33 +3,55 @@ use sdl2::video::{Window, GLContext};
 
 use crate::{gl, WIDTH, HEIGHT};
 use emscripten_main_loop::MainLoopEvent;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
 use crate::cube::Cube;
-use cgmath::{Vector3, Point3, Matrix4, perspective, Deg, vec3, MetricSpace, Zero};
+use cgmath::{Vector3, Vector4, Point3, Matrix4, perspective, Deg, vec3, MetricSpace, Zero, InnerSpace};
 use crate::flying_camera::{Flying_Camera, PERSPECTIVE_ANGLE};
-use crate::flying_camera::Flying_Camera_Movement::{UP, DOWN, LEFT, RIGHT, FORWARD};
+use crate::flying_camera::Flying_Camera_Movement::{UP, DOWN, LEFT, RIGHT};
 use crate::gl_helper::model::Model;
 use crate::gl_helper::instance_model::ModelInstance;
-use crate::alien_army::AlienArmy;
+use crate::alien_army::{AlienArmy, AlienAnimation, AlienAnimations, SECONDS_PER_DELTA};
 use std::time::Instant;
+use std::collections::HashMap;
 use crate::bullets::Bullets;
 use crate::explosion::Explosions;
 #[cfg(target_os = "emscripten")]
-use crate::handle_javascript::start_javascript_play_sound;
-#[cfg(target_os = "emscripten")]
 use crate::handle_javascript::start_game;
 #[cfg(target_os = "emscripten")]
 use crate::handle_javascript::end_game;
 use crate::handle_javascript::{write_stats_data};
 use std::ffi::CString;
+use crate::stats::{RunStats, STATS_SUMMARY_FILE};
+use crate::settings::{Settings, SETTINGS_FILE};
+use crate::input::{Input, Bindings, Action, OptionsMenu, MenuResult, MouseSettings};
+use crate::player::{PlayerController, EYE_HEIGHT, PLAYER_RADIUS};
+use crate::arena::{ForceField, ARENA_HALF_SIZE, ARENA_HEIGHT};
+use crate::view_camera::{ViewCamera, CameraMode};
+use crate::radar::Radar;
+use crate::display::{DisplaySettings, FrameLimiter};
+use crate::hud::{Hud, HudInfo, TTF_FONT};
+use crate::audio::{Audio, AudioSettings, Listener, Sound, LOOP_CHANNELS};
+use crate::music::AdaptiveMusic;
+use crate::gl_helper::lighting::{Lighting, PointLight};
+use crate::gl_helper::shadow::{ShadowMap, ShadowSettings};
+use crate::gl_helper::instancing::{take_draw_calls, WHITE_TINT};
+use crate::skybox::Skybox;
+use crate::gl_helper::camera_uniforms::CameraUniforms;
+use crate::gl_helper::assets::Assets;
+use std::rc::Rc;
+use crate::levels::{Levels, LEVELS_FILE};
+use sdl2::mixer::Channel;
 
 pub const GRID_WIDTH: i32 = 48;
 pub const SCALE: f32 = 0.25;
 pub const GROUND: f32 = 0.0;
 pub const ROW_SIZE: usize = 5;
-const BULLET_RADIUS: f32 = 0.04;
-pub const ALIEN_RADIUS: f32 = 0.03;
+/// A little bigger than the bullet is drawn, hits are when it touches an alien's own radius
+const BULLET_RADIUS: f32 = 0.01;
+const HIT_TINT: Vector4<f32> = Vector4 { x: 1.0, y: 0.35, z: 0.35, w: 1.0 };
+const WEAPON: &str = "laser";
+/// Only the closest diving aliens are heard, one for each loop channel
+const MAX_DIVE_SOUNDS: usize = LOOP_CHANNELS as usize;
 
 pub struct Runtime {
     loaded: bool,
@@ -39,24 +61,43 @@ pub struct Runtime {
     sdl: Sdl,
     _video: VideoSubsystem,
     window: Window,
+    pub display: DisplaySettings,
+    frame_limiter: FrameLimiter,
+    pub audio: Audio,
+    music: AdaptiveMusic,
+    /// Looping sound for each diving alien that can be heard, by alien id
+    dive_sounds: HashMap<u128, Channel>,
+    pub viewport: (u32, u32),
     _gl_context: GLContext,
     pub gl: std::rc::Rc<gl::Gl>,
     pub camera: Flying_Camera,
-    pub left: bool,
-    pub right: bool,
-    pub up: bool,
-    pub down: bool,
-    pub forward: bool,
-    pub fire: bool,
+    pub player: PlayerController,
+    view_camera: ViewCamera,
+    hud: Hud,
+    radar: Radar,
+    pub settings: Settings,
+    pub input: Input,
+    options_menu: OptionsMenu,
+    paused: bool,
+    mouse_captured: bool,
     last_fire_countdown:i32,
     pub ground: Ground,
-    pub alien_model: Model,
+    lighting: Lighting,
+    /// None when shadows are turned off or the shadow map could not be made
+    shadow_map: Option<ShadowMap>,
+    camera_uniforms: CameraUniforms,
+    skybox: Skybox,
+    levels: Levels,
+    force_field: ForceField,
     alien_army: AlienArmy,
     bullets: Bullets,
     explosions: Explosions,
     exploding: AlienArmy,
     score:i32,
     level:i32,
+    stats: RunStats,
+    /// Models, textures and shaders loaded once and shared
+    assets: Assets,
 }
 
 
@@ -101,13 +142,15 @@ impl MovementAndCollision {
             ..MovementAndCollision::default()
         }
     }
+    /// True when the two spheres overlap
     pub fn hit_other(&self, other: &MovementAndCollision) -> bool {
-        self.position.distance(other.position) < self.radius
+        self.position.distance(other.position) < self.radius + other.radius
     }
 }
 
+/// View and projection come from the camera uniform buffer, set once a frame
 pub(crate) trait Render {
-    fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>);
+    fn render(&mut self, gl: &gl::Gl);
 }
 
 pub(crate) trait Update {
@@ -120,12 +163,17 @@ pub struct Ground {
 }
 
 impl Ground {
-    pub fn new(gl: &gl::Gl) -> Ground {
-        let cube = Cube::new(&gl, "resources/ground.png", vec3(2.0, 0.001, 2.0), 40.0);
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Ground {
+        let cube = Cube::new_shadowed(&gl, assets, "resources/ground.png", vec3(ARENA_HALF_SIZE, 0.001, ARENA_HALF_SIZE), 40.0);
         Ground {
             cube
         }
     }
+
+    pub fn render_shadowed(&mut self, gl: &gl::Gl, shadow: Option<&ShadowMap>) {
+        let matrix = Matrix4::<f32>::from_translation(Vector3::zero());
+        self.cube.render_shadowed(gl, &matrix, shadow);
+    }
 }
 
 impl Update for Ground {
@@ -133,9 +181,8 @@ impl Update for Ground {
 }
 
 impl Render for Ground {
-    fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
-        let matrix = Matrix4::<f32>::from_translation(Vector3::zero());
-        self.cube.render(gl, &matrix, view, projection);
+    fn render(&mut self, gl: &gl::Gl) {
+        self.render_shadowed(gl, None);
     }
 }
 
@@ -147,6 +194,8 @@ pub struct MovingAlien {
     pub(crate) drop_down: bool,
     pub(crate) landed: bool,
     pub time_to_live: i32,
+    animation: AlienAnimation,
+    pub(crate) animations: AlienAnimations,
 }
 
 impl PartialEq for MovingAlien {
@@ -156,10 +205,9 @@ impl PartialEq for MovingAlien {
 }
 
 impl MovingAlien {
-    pub fn new(model: &Model, position: Vector3<f32>, radius: f32, scale: f32) -> MovingAlien {
-        //let alien_model =  Model::new(&gl,"resources/models/alien.obj");
-
-        let alien = ModelInstance::new(model.clone(), scale);
+    pub fn new(model: &Rc<Model>, animations: AlienAnimations, position: Vector3<f32>, radius: f32, scale: f32) -> MovingAlien {
+        let mut alien = ModelInstance::new(Rc::clone(model), scale);
+        alien.play(animations.index(AlienAnimation::March), true);
         MovingAlien {
             movement_collision: MovementAndCollision::new(radius, position),
             model_instance: alien,
@@ -167,12 +215,43 @@ impl MovingAlien {
             drop_down: false,
             landed: false,
             time_to_live: 0,
+            animation: AlienAnimation::March,
+            animations,
+        }
+    }
+}
+
+impl MovingAlien {
+    /// name used when recording kills in the run statistics
+    pub fn kind(&self) -> &'static str {
+        if self.drop_down { "diving" } else { "marching" }
+    }
+
+    /// Starts an animation unless it is already playing
+    pub fn set_animation(&mut self, animation: AlienAnimation) {
+        if self.animation != animation {
+            self.animation = animation;
+            self.model_instance.play(self.animations.index(animation), animation.loops());
+            // flash red while hit
+            self.model_instance.tint = if animation == AlienAnimation::Hit { HIT_TINT } else { WHITE_TINT };
         }
     }
 }
 
 impl Update for MovingAlien {
-    fn update(&mut self, _delta: f32) {}
+    /// A hit plays through before the alien goes back to marching or diving, death stays at its end
+    fn update(&mut self, delta: f32) {
+        let finished = self.model_instance.advance(delta * SECONDS_PER_DELTA);
+        let busy = match self.animation {
+            AlienAnimation::Death => true,
+            AlienAnimation::Hit => !finished,
+            _ => false,
+        };
+        if !busy {
+            let behaviour = if self.drop_down && !self.landed { AlienAnimation::Dive } else { AlienAnimation::March };
+            self.set_animation(behaviour);
+        }
+    }
 }
 
 impl Runtime {
@@ -190,32 +269,53 @@ impl Runtime {
         video.gl_attr().set_context_major_version(context_params.1);
         video.gl_attr().set_context_minor_version(context_params.2);
 
+        let settings = Settings::load(SETTINGS_FILE);
+        let display = DisplaySettings::from_settings(&settings);
+
         // Create a window
-        let window = video
-            .window("rust-invaders", WIDTH, HEIGHT)
+        let mut window = video
+            .window("rust-invaders", display.width, display.height)
             .resizable()
+            .allow_highdpi()
             .opengl()
             .position_centered()
             .build().unwrap();
+        display.apply_window_mode(&mut window);
 
 
         let gl_context = window.gl_create_context().unwrap();
+        display.apply_vsync(&video);
         let gl_orig: std::rc::Rc<gl::Gl> = std::rc::Rc::new(gl::Gl::load_with(|s| { video.gl_get_proc_address(s) as *const _ }));
 
         let gl = std::rc::Rc::clone(&gl_orig);
 
-        let ground = Ground::new(&gl);
+        let mut assets = Assets::new();
+        let ground = Ground::new(&gl, &mut assets);
+        let levels = Levels::load(LEVELS_FILE);
+        let mut skybox = Skybox::new(&gl, &mut assets);
+        skybox.set_sky(&gl, &mut assets, levels.sky(0));
+        let shadow_settings = ShadowSettings::from_settings(&settings);
+        let shadow_map = if shadow_settings.enabled {
+            ShadowMap::new(&gl, &mut assets, shadow_settings.size).map_err(|e| println!("no shadows, {}", e)).ok()
+        } else {
+            None
+        };
+
+        let mut audio = Audio::new(&sdl, AudioSettings::from_settings(&settings));
+        audio.load_sound_effects();
+        let music = AdaptiveMusic::new(&audio);
+
+        let player = PlayerController::from_settings(&settings);
+        let input = Input::new(Bindings::from_settings(&settings), MouseSettings::from_settings(&settings));
 
         let camera = Flying_Camera {
-            Position: Point3::new(0.0, 0.05, 0.0),
+            Position: Point3::new(0.0, EYE_HEIGHT, 0.0),
             ..Flying_Camera::default()
         };
 
-        let alien_model = Model::new(&gl, "resources/models/anotheralien.obj","resources/models/anotheralien.png");
-
         unsafe { gl.Enable(gl::BLEND); }
 
-        let runtime = Runtime {
+        let mut runtime = Runtime {
             loaded: false,
             now: Instant::now(),
             last: 0,
@@ -223,25 +323,43 @@ impl Runtime {
             sdl,
             _video: video,
             window,
+            display,
+            frame_limiter: FrameLimiter::new(),
+            audio,
+            music,
+            dive_sounds: HashMap::new(),
+            viewport: (0, 0),
             _gl_context: gl_context,
             gl: gl_orig,
             camera,
+            player,
+            view_camera: ViewCamera::new(&gl, &mut assets),
+            radar: Radar::new(&gl, &mut assets).unwrap_or_else(|e| panic!("unable to make the radar {}", e)),
+            hud: Hud::new(&gl, &mut assets, if settings.get_or("hud.ttf", true) { Some(TTF_FONT) } else { None })
+                .unwrap_or_else(|e| panic!("unable to make the HUD {}", e)),
             ground,
-            left: false,
-            right: false,
-            up: false,
-            down: false,
-            forward: false,
-            fire: false,
-            alien_model,
-            alien_army: AlienArmy::new(&gl),
-            bullets: Bullets::new(&gl),
-            explosions: Explosions::new(&gl),
-            exploding: AlienArmy::new(&gl),
+            lighting: Lighting::new(),
+            shadow_map,
+            camera_uniforms: CameraUniforms::new(&gl),
+            skybox,
+            levels,
+            force_field: ForceField::new(&gl, &mut assets),
+            settings,
+            input,
+            options_menu: OptionsMenu::new(),
+            paused: false,
+            mouse_captured: false,
+            alien_army: AlienArmy::new(&gl, &mut assets),
+            bullets: Bullets::new(&gl, &mut assets),
+            explosions: Explosions::new(&gl, &mut assets),
+            exploding: AlienArmy::new(&gl, &mut assets),
             last_fire_countdown: 0,
             score:0,
             level:0,
+            stats: RunStats::new(),
+            assets,
         };
+        runtime.print_assets();
         runtime
     }
 }
@@ -255,9 +373,10 @@ impl emscripten_main_loop::MainLoop for Runtime {
                 start_game();
             }
         }
+        self.update_viewport();
         let projection: Matrix4<f32> =
-            perspective(Deg(PERSPECTIVE_ANGLE), WIDTH as f32 / HEIGHT as f32, 0.01, 40.0);
-        let view = self.camera.GetViewMatrix();
+            perspective(Deg(PERSPECTIVE_ANGLE), self.aspect_ratio(), 0.01, 40.0);
+        let view = self.view_camera.view_matrix(&self.camera);
 
         unsafe {
             self.gl.Enable(gl::DEPTH_TEST);
@@ -277,50 +396,161 @@ impl emscripten_main_loop::MainLoop for Runtime {
             delta = 1.0;
         }
 
-        self.ground.render(&self.gl, &view, &projection);
-        self.ground.update(delta);
+        // the world moves on before it is drawn, the player after the events of this frame
+        let playing = !self.paused && !self.options_menu.open;
+        if playing {
+            self.update_world(delta);
+        }
+
+        take_draw_calls();
+        self.update_lighting();
+        self.render_shadow_map();
+        self.camera_uniforms.set(&self.gl, &view, &projection);
+        self.skybox.render(&self.gl, self.lighting.sun_direction);
+        self.ground.render_shadowed(&self.gl, self.shadow_map.as_ref());
+        self.alien_army.render(&self.gl);
+        self.bullets.render(&self.gl);
+        self.exploding.render(&self.gl);
+        self.explosions.render(&self.gl);
+        self.view_camera.render_player(&self.gl, &self.camera);
+        self.force_field.render(&self.gl);
+        self.render_radar(&view, &projection);
+        let draw_calls = take_draw_calls();
+        let hud_info = HudInfo {
+            score: self.score,
+            level: self.level,
+            lives: None,
+            weapon: WEAPON,
+            paused: self.paused,
+            menu: if self.options_menu.open { Some(self.options_menu.lines(&self.input.bindings)) } else { None },
+            draw_calls: if self.view_camera.is_debug() { Some(draw_calls) } else { None },
+        };
+        self.hud.render(&self.gl, self.viewport, &hud_info);
+        // the HUD leaves its own draws out of what it shows, the printed count is the whole frame
+        #[cfg(not(target_os = "emscripten"))]
+        let draw_calls = draw_calls + take_draw_calls();
+        self.window.gl_swap_window();
+        #[cfg(not(target_os = "emscripten"))]
+        self.frame_limiter.wait(self.display.frame_cap);
+
+        let end_status = self.handle_keyboard();
+
+        let playing = !self.paused && !self.options_menu.open;
+        if self.input.mouse.look && playing != self.mouse_captured {
+            self.mouse_captured = playing;
+            self.sdl.mouse().set_relative_mouse_mode(playing);
+        }
+        self.audio.pause_effects(!playing);
+        if playing {
+            self.update_game(delta);
+        }
+
+        let update:String = self.stats.to_json(self.level, self.score);
+
+        #[cfg(not(target_os = "emscripten"))]
+        if self.last_fire_countdown % 60 == 0 {
+            println!("{} draws {}",self.stats.summary_line(self.level, self.score), draw_calls);
+        }
+
+        write_stats_data(CString::new(update).to_owned().unwrap().as_ptr());
+
+        match end_status {
+            MainLoopEvent::Terminate => {
+                #[cfg(not(target_os = "emscripten"))]
+                self.stats.write_summary(STATS_SUMMARY_FILE, self.level, self.score);
+                #[cfg(target_os = "emscripten")]
+                    unsafe {
+                    end_game();
+                }
+            }
+            MainLoopEvent::Continue => {}
+        }
+
+        end_status
+    }
+}
+
+impl Runtime {
+    /// Follows the size of the drawable area in pixels, which on high DPI displays is bigger than the
+    /// window size. Checked every frame as a browser resizing the canvas does not always send an SDL event.
+    fn update_viewport(&mut self) {
+        let (width, height) = self.window.drawable_size();
+        if width > 0 && height > 0 && (width, height) != self.viewport {
+            self.viewport = (width, height);
+            unsafe {
+                self.gl.Viewport(0, 0, width as i32, height as i32);
+            }
+        }
+    }
 
+    pub fn aspect_ratio(&self) -> f32 {
+        if self.viewport.1 == 0 {
+            WIDTH as f32 / HEIGHT as f32
+        } else {
+            self.viewport.0 as f32 / self.viewport.1 as f32
+        }
+    }
+
+    fn render_radar(&mut self, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let hud_projection = Hud::projection(self.viewport);
+        let scale = self.hud.font.scale_for(self.viewport);
+        Hud::begin_pass(&self.gl);
+        if self.view_camera.mode == CameraMode::FirstPerson {
+            self.radar.render_crosshair(&self.gl, &hud_projection, self.viewport, scale);
+        }
+        self.radar.render_radar(&self.gl, &hud_projection, self.viewport, scale, &self.camera, &self.alien_army.all_aliens);
+        self.radar.render_warnings(&self.gl, &hud_projection, self.viewport, scale, &(projection * view), &self.alien_army.all_aliens);
+        Hud::end_pass(&self.gl);
+    }
+
+    fn update_world(&mut self, delta: f32) {
+        self.ground.update(delta);
         self.alien_army.update(delta);
-        self.alien_army.render(&self.gl, &view, &projection);
         self.bullets.update(delta);
-        self.bullets.render(&self.gl, &view, &projection);
-
         self.exploding.update(delta);
-        self.exploding.render(&self.gl, &view, &projection);
         self.explosions.update(delta);
-        self.explosions.render(&self.gl, &view, &projection);
-        self.window.gl_swap_window();
+    }
 
+    fn update_game(&mut self, delta: f32) {
         self.camera.save_position();
-        if self.up { self.camera.processKeyboard(UP, delta); }
-        if self.down { self.camera.processKeyboard(DOWN, delta); }
-        if self.left { self.camera.processKeyboard(LEFT, delta); }
-        if self.right { self.camera.processKeyboard(RIGHT, delta); }
-        if self.forward { self.camera.processKeyboard(FORWARD, delta); }
+        let (mouse_x, mouse_y) = self.input.take_mouse_motion();
+        if self.view_camera.is_debug() {
+            self.view_camera.update_debug(&self.input, delta);
+            self.view_camera.mouse_look(mouse_x as f32, mouse_y as f32, self.input.mouse.sensitivity, self.input.mouse.invert_y);
+        } else {
+            if self.input.held(Action::PitchUp) { self.camera.processKeyboard(UP, delta); }
+            if self.input.held(Action::PitchDown) { self.camera.processKeyboard(DOWN, delta); }
+            if self.input.held(Action::TurnLeft) { self.camera.processKeyboard(LEFT, delta); }
+            if self.input.held(Action::TurnRight) { self.camera.processKeyboard(RIGHT, delta); }
+            if mouse_x != 0 || mouse_y != 0 {
+                self.camera.processMouseMovement(mouse_x as f32, mouse_y as f32, self.input.mouse.sensitivity, self.input.mouse.invert_y);
+            }
+            self.player.update(&mut self.camera, &self.input, delta);
+        }
 
         self.last_fire_countdown = self.last_fire_countdown -1;
         if self.last_fire_countdown < -10000 {
             self.last_fire_countdown = -1;
         }
-        if self.fire && self.last_fire_countdown <= 0 {
+        if self.input.held(Action::Fire) && !self.view_camera.is_debug() && self.last_fire_countdown <= 0 {
             self.last_fire_countdown = 30;
+            self.stats.shot_fired();
             let direction = vec3(self.camera.Front.x, self.camera.Front.y, self.camera.Front.z);
             let here = vec3(self.camera.Position.x, self.camera.Position.y, self.camera.Position.z);
             self.bullets.fire(here, direction, delta, BULLET_RADIUS);
+            self.audio.play_at(Sound::Shot, here + direction * BULLET_RADIUS, &Listener::from_camera(&self.camera));
         }
 
-
-        let end_status = self.handle_keyboard();
-
-        let camera_collision = MovementAndCollision::new(0.07, vec3(self.camera.Position.x, self.camera.Position.y, self.camera.Position.z));
+        let camera_collision = MovementAndCollision::new(PLAYER_RADIUS, vec3(self.camera.Position.x, self.camera.Position.y, self.camera.Position.z));
 
 
         let mut alien_remove: Vec<usize> = Vec::new();
+        let mut alien_shot: Vec<usize> = Vec::new();
         let mut alien_collide: Vec<usize> = Vec::new();
         for i in (0..self.alien_army.all_aliens.len()).rev() {
             let s = self.alien_army.all_aliens.get(i).unwrap();
             if camera_collision.hit_other(&s.movement_collision) {
-                self.camera.rollback();
+                self.player.collide_with(&mut self.camera, s.movement_collision.position, s.movement_collision.radius);
             }
 
 
@@ -329,7 +559,9 @@ impl emscripten_main_loop::MainLoop for Runtime {
                 if b.collision.hit_other(&s.movement_collision) {
                     self.bullets.instances.remove(bullet_index);
                     alien_remove.push(i);
+                    alien_shot.push(i);
                     self.score = self.score +1;
+                    self.stats.hit();
                 }
             }
             for other_aliens in (0..self.alien_army.all_aliens.len()).rev() {
@@ -345,24 +577,33 @@ impl emscripten_main_loop::MainLoop for Runtime {
         for i in alien_collide {
             let a = self.alien_army.all_aliens.get_mut(i).unwrap();
             a.drop_down = true;
+            a.set_animation(AlienAnimation::Hit);
         }
-        for i in alien_remove {
+        // an alien hit by two bullets or two others is only removed once, highest index first
+        // so the ones still to go keep their place
+        alien_remove.sort_unstable();
+        alien_remove.dedup();
+        for i in alien_remove.into_iter().rev() {
             let mut alien = self.alien_army.all_aliens.get(i).unwrap().clone();
             alien.time_to_live = 60;
+            alien.set_animation(AlienAnimation::Death);
+            if alien_shot.contains(&i) {
+                self.stats.killed(alien.kind());
+            }
             let position = alien.movement_collision.position.clone();
             self.exploding.all_aliens.push(alien);
 
             self.alien_army.all_aliens.remove(i);
             self.explosions.create(position);
-            #[cfg(target_os = "emscripten")]
-                unsafe {
-                start_javascript_play_sound(1);
-            }
+            self.audio.play_at(Sound::Hit, position, &Listener::from_camera(&self.camera));
         }
         for i in (0..self.exploding.all_aliens.len()).rev() {
             let mut alien = self.exploding.all_aliens.get_mut(i).unwrap();
             alien.time_to_live = alien.time_to_live - 1;
-            alien.model_instance.scale = alien.model_instance.scale * 0.9;
+            // the death animation shrinks it away, a model without one is shrunk here
+            if alien.animations.index(AlienAnimation::Death).is_none() {
+                alien.model_instance.scale = alien.model_instance.scale * 0.9;
+            }
             if alien.time_to_live <= 0 {
                 self.exploding.all_aliens.remove(i);
             }
@@ -372,71 +613,162 @@ impl emscripten_main_loop::MainLoop for Runtime {
         if  create {
             self.score = self.score - landed as i32;
             self.level = self.level +1;
+            self.stats.new_wave();
+            self.hud.message(&format!("WAVE {}", self.level));
+            self.skybox.set_sky(&self.gl, &mut self.assets, self.levels.sky(self.level));
+            self.print_assets();
+            self.audio.play(Sound::PowerUp);
         }
+        self.update_dive_sounds();
+        self.music.update(&self.audio, &self.alien_army);
+        self.stats.landings = self.alien_army.landings;
+        self.stats.distance_travelled = self.stats.distance_travelled + self.camera.Position.distance(self.camera.PreviousPosition);
+    }
 
-        let mut list: Vec<String> = Vec::new();
-        list.push(format!("level {} score {}",self.level, self.score));
-        let update:String =list.join("\n") ;
+    /// Lists the loaded assets when the game starts and again whenever more are loaded
+    fn print_assets(&mut self) {
+        if let Some(report) = self.assets.take_report() {
+            for line in report {
+                println!("{}", line);
+            }
+        }
+    }
 
-        #[cfg(not(target_os = "emscripten"))]
-        if self.last_fire_countdown % 60 == 0 {
-            println!("{}",update);
+    /// Bullets and explosions light up the aliens around them
+    fn update_lighting(&mut self) {
+        let eye = self.view_camera.eye(&self.camera);
+        self.lighting.eye = vec3(eye.x, eye.y, eye.z);
+
+        let mut lights: Vec<PointLight> = self.bullets.instances.iter().map(|b| PointLight {
+            position: b.collision.position,
+            colour: vec3(0.4, 1.0, 0.4),
+            radius: 0.3,
+        }).collect();
+        for fire in &self.explosions.instances {
+            // an explosion is lots of pieces close together, one light does for them all
+            if lights.iter().all(|l| l.position.distance(fire.position) > 0.1) {
+                lights.push(PointLight { position: fire.position, colour: vec3(1.0, 0.6, 0.2), radius: 0.6 });
+            }
         }
+        self.lighting.set_point_lights(lights);
+        self.lighting.apply(&self.gl, &[self.alien_army.shader(), self.exploding.shader(), self.view_camera.shader()]);
+    }
 
-        write_stats_data(CString::new(update).to_owned().unwrap().as_ptr());
+    /// Aliens and bullets cast shadows on the ground, which helps to judge how high they are
+    fn render_shadow_map(&mut self) {
+        let shadow = match self.shadow_map.as_mut() {
+            Some(shadow) => shadow,
+            None => return,
+        };
+        // the whole arena, seen from the sun its corners are the furthest out
+        let half_size = ARENA_HALF_SIZE * 2.0f32.sqrt() + ARENA_HEIGHT;
+        shadow.aim(self.lighting.sun_direction, Vector3::zero(), half_size);
+        shadow.begin(&self.gl);
+        self.alien_army.render_shadow(&self.gl, shadow);
+        self.exploding.render_shadow(&self.gl, shadow);
+        self.bullets.render_shadow(&self.gl, shadow);
+        shadow.end(&self.gl, self.viewport);
+    }
 
-        match end_status {
-            MainLoopEvent::Terminate => {
-                #[cfg(target_os = "emscripten")]
-                    unsafe {
-                    end_game();
-                }
+    /// Starts, moves and stops the whine of the diving aliens closest to the player
+    fn update_dive_sounds(&mut self) {
+        let listener = Listener::from_camera(&self.camera);
+        let mut diving: Vec<(u128, Vector3<f32>)> = self.alien_army.all_aliens.iter()
+            .filter(|a| a.drop_down && !a.landed)
+            .map(|a| (a.movement_collision.id, a.movement_collision.position))
+            .collect();
+        diving.sort_by(|a, b| {
+            let (da, db) = ((a.1 - listener.position).magnitude2(), (b.1 - listener.position).magnitude2());
+            da.partial_cmp(&db).unwrap()
+        });
+        diving.truncate(MAX_DIVE_SOUNDS);
+
+        let audio = &mut self.audio;
+        self.dive_sounds.retain(|id, channel| {
+            let still_diving = diving.iter().any(|(d, _)| d == id);
+            if !still_diving {
+                audio.stop(*channel);
+            }
+            still_diving
+        });
+        for (id, position) in diving {
+            match self.dive_sounds.get(&id) {
+                Some(channel) => self.audio.set_position(*channel, position, &listener),
+                None => if let Some(channel) = self.audio.loop_at(Sound::Dive, position, &listener) {
+                    self.dive_sounds.insert(id, channel);
+                },
             }
-            MainLoopEvent::Continue => {}
         }
-
-        end_status
     }
-}
 
-impl Runtime {
     fn handle_keyboard(&mut self) -> MainLoopEvent {
         let mut return_status = emscripten_main_loop::MainLoopEvent::Continue;
         let mut events = self.sdl.event_pump().unwrap();
 
         for event in events.poll_iter() {
             match event {
-                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                Event::Quit { .. } => {
                     return_status = emscripten_main_loop::MainLoopEvent::Terminate;
                 }
-                Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
-                    self.left = true;
-                    self.right = false;
+                Event::KeyDown { keycode: Some(key), .. } if self.options_menu.open => {
+                    self.audio.play(Sound::Select);
+                    match self.options_menu.handle_key(key, &mut self.input.bindings) {
+                        MenuResult::Changed => {
+                            self.input.bindings.write_to(&mut self.settings);
+                            self.settings.save();
+                        }
+                        MenuResult::Closed | MenuResult::Nothing => {}
+                    }
+                }
+                Event::KeyDown { keycode: Some(key), repeat, .. } => {
+                    for action in self.input.key_down(key, repeat) {
+                        match action {
+                            Action::Quit => {
+                                return_status = emscripten_main_loop::MainLoopEvent::Terminate;
+                            }
+                            Action::CameraMode => {
+                                self.view_camera.next_mode();
+                                self.hud.message(&format!("VIEW {:?}", self.view_camera.mode).to_uppercase());
+                            }
+                            Action::DebugCamera => {
+                                self.view_camera.toggle_debug(&self.camera);
+                                self.hud.message(&format!("VIEW {:?}", self.view_camera.mode).to_uppercase());
+                            }
+                            Action::ToggleFullscreen => {
+                                self.display.next_mode();
+                                self.display.apply_window_mode(&mut self.window);
+                                self.display.write_to(&mut self.settings);
+                                self.settings.save();
+                            }
+                            Action::Pause => {
+                                self.paused = !self.paused;
+                                // motion from before the pause must not turn the player after it
+                                self.input.take_mouse_motion();
+                            }
+                            Action::Options => {
+                                self.input.release_all();
+                                self.options_menu.show();
+                                self.audio.play(Sound::Select);
+                            }
+                            _ => {}
+                        }
+                    }
                 }
-                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
-                    self.right = true;
-                    self.left = false;
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    self.input.key_up(key);
                 }
-                Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
-                    self.up = true;
-                    self.down = false
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    self.update_viewport();
                 }
-                Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
-                    self.down = true;
-                    self.up = false
+                Event::MouseMotion { xrel, yrel, .. } if !self.paused && !self.options_menu.open => {
+                    self.input.mouse_moved(xrel, yrel);
                 }
-                Event::KeyDown { keycode: Some(Keycode::LShift), .. } => {
-                    self.forward = true;
+                Event::MouseButtonDown { mouse_btn, .. } if !self.options_menu.open => {
+                    self.input.mouse_down(mouse_btn);
                 }
-                Event::KeyDown { keycode: Some(Keycode::Space), .. } => {
-                    self.fire = true;
+                Event::MouseButtonUp { mouse_btn, .. } => {
+                    self.input.mouse_up(mouse_btn);
                 }
-                Event::KeyUp { keycode: Some(Keycode::Left), .. } => { self.left = false; }
-                Event::KeyUp { keycode: Some(Keycode::Right), .. } => { self.right = false; }
-                Event::KeyUp { keycode: Some(Keycode::Up), .. } => { self.up = false }
-                Event::KeyUp { keycode: Some(Keycode::Down), .. } => { self.down = false }
-                Event::KeyUp { keycode: Some(Keycode::LShift), .. } => { self.forward = false }
-                Event::KeyUp { keycode: Some(Keycode::Space), .. } => { self.fire = false }
 
                 _ => {}
             }
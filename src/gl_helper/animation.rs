@@ -0,0 +1,250 @@
+use cgmath::{vec3, Matrix4, Quaternion, Vector3, Vector4, InnerSpace, One};
+
+/// A node of a model's hierarchy, meshes hang off nodes and animations move them
+#[derive(Clone)]
+pub struct Node {
+    pub parent: Option<usize>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node {
+            parent: None,
+            translation: vec3(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Node {
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation) *
+            Matrix4::from(self.rotation) *
+            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// Model space matrix of every node, parents applied
+pub fn global_matrices(nodes: &[Node]) -> Vec<Matrix4<f32>> {
+    let mut globals: Vec<Option<Matrix4<f32>>> = vec![None; nodes.len()];
+    let mut visiting = vec![false; nodes.len()];
+    for i in 0..nodes.len() {
+        global_matrix(nodes, i, &mut globals, &mut visiting);
+    }
+    globals.into_iter().map(|m| m.unwrap()).collect()
+}
+
+fn global_matrix(nodes: &[Node], i: usize, globals: &mut Vec<Option<Matrix4<f32>>>, visiting: &mut Vec<bool>) -> Matrix4<f32> {
+    if let Some(matrix) = globals[i] {
+        return matrix;
+    }
+    visiting[i] = true;
+    let local = nodes[i].local_matrix();
+    let matrix = match nodes[i].parent {
+        // only a broken file has parents that loop back round, the loop is cut where it closes
+        Some(parent) if !visiting[parent] => global_matrix(nodes, parent, globals, visiting) * local,
+        _ => local,
+    };
+    visiting[i] = false;
+    globals[i] = Some(matrix);
+    matrix
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Interpolation {
+    Step,
+    Linear,
+}
+
+/// Key frames for one property of one node. Translations and scales only use xyz,
+/// rotations are quaternions stored x, y, z, w.
+#[derive(Clone)]
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: Vec<Vector4<f32>>,
+}
+
+impl Channel {
+    fn sample(&self, time: f32) -> Vector4<f32> {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] || last == 0 {
+            return self.values[0];
+        }
+        if time >= self.times[last] {
+            return self.values[last];
+        }
+        let next = self.times.iter().position(|t| *t > time).unwrap();
+        let (from, to) = (next - 1, next);
+        if self.interpolation == Interpolation::Step {
+            return self.values[from];
+        }
+        let amount = (time - self.times[from]) / (self.times[to] - self.times[from]);
+        let (a, b) = (self.values[from], self.values[to]);
+        match self.property {
+            Property::Rotation => {
+                let (from, mut to) = (quaternion(a), quaternion(b));
+                // go the short way round
+                if from.dot(to) < 0.0 {
+                    to = -to;
+                }
+                let q = from.slerp(to, amount);
+                Vector4::new(q.v.x, q.v.y, q.v.z, q.s)
+            }
+            _ => a.lerp(b, amount),
+        }
+    }
+}
+
+fn quaternion(v: Vector4<f32>) -> Quaternion<f32> {
+    Quaternion::new(v.w, v.x, v.y, v.z)
+}
+
+/// A named set of channels, such as a walk cycle
+#[derive(Clone)]
+pub struct Animation {
+    pub name: String,
+    /// Seconds from the first key frame to the last
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    /// Moves the nodes to where they are at this many seconds into the animation
+    pub fn apply(&self, time: f32, nodes: &mut [Node]) {
+        for channel in &self.channels {
+            if channel.times.is_empty() || channel.node >= nodes.len() {
+                continue;
+            }
+            let value = channel.sample(time);
+            let node = &mut nodes[channel.node];
+            match channel.property {
+                Property::Translation => node.translation = value.truncate(),
+                Property::Rotation => node.rotation = quaternion(value).normalize(),
+                Property::Scale => node.scale = value.truncate(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{vec4, Deg, Rotation3};
+
+    fn channel(property: Property, interpolation: Interpolation, keys: &[(f32, Vector4<f32>)]) -> Channel {
+        Channel {
+            node: 0,
+            property,
+            interpolation,
+            times: keys.iter().map(|k| k.0).collect(),
+            values: keys.iter().map(|k| k.1).collect(),
+        }
+    }
+
+    fn translation(keys: &[(f32, Vector4<f32>)], interpolation: Interpolation) -> Channel {
+        channel(Property::Translation, interpolation, keys)
+    }
+
+    #[test]
+    fn sample_holds_the_first_and_last_keys() {
+        let c = translation(&[(1.0, vec4(1.0, 0.0, 0.0, 0.0)), (2.0, vec4(3.0, 0.0, 0.0, 0.0))], Interpolation::Linear);
+        assert_eq!(c.sample(0.0), vec4(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(c.sample(1.0), vec4(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(c.sample(2.0), vec4(3.0, 0.0, 0.0, 0.0));
+        assert_eq!(c.sample(10.0), vec4(3.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sample_of_a_single_key() {
+        let c = translation(&[(0.5, vec4(2.0, 2.0, 2.0, 0.0))], Interpolation::Linear);
+        assert_eq!(c.sample(0.0), vec4(2.0, 2.0, 2.0, 0.0));
+        assert_eq!(c.sample(5.0), vec4(2.0, 2.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn sample_between_keys() {
+        let keys = [(0.0, vec4(0.0, 0.0, 0.0, 0.0)), (1.0, vec4(4.0, 0.0, 0.0, 0.0)), (3.0, vec4(0.0, 2.0, 0.0, 0.0))];
+        let linear = translation(&keys, Interpolation::Linear);
+        assert_eq!(linear.sample(0.25), vec4(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(linear.sample(2.0), vec4(2.0, 1.0, 0.0, 0.0));
+        let step = translation(&keys, Interpolation::Step);
+        assert_eq!(step.sample(0.99), vec4(0.0, 0.0, 0.0, 0.0));
+        assert_eq!(step.sample(2.0), vec4(4.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sample_turns_the_short_way_round() {
+        let from = Quaternion::from_angle_y(Deg(10.0));
+        // the same rotation as 20 degrees, stored the other way up
+        let to = -Quaternion::from_angle_y(Deg(20.0));
+        let c = channel(Property::Rotation, Interpolation::Linear, &[
+            (0.0, vec4(from.v.x, from.v.y, from.v.z, from.s)),
+            (1.0, vec4(to.v.x, to.v.y, to.v.z, to.s)),
+        ]);
+        let half = quaternion(c.sample(0.5));
+        let expected = Quaternion::from_angle_y(Deg(15.0));
+        assert!(half.dot(expected).abs() > 0.9999);
+    }
+
+    #[test]
+    fn apply_skips_channels_without_keys_or_nodes() {
+        let mut nodes = vec![Node::default()];
+        let mut missing_node = translation(&[(0.0, vec4(5.0, 5.0, 5.0, 0.0))], Interpolation::Linear);
+        missing_node.node = 3;
+        let animation = Animation {
+            name: "test".to_string(),
+            duration: 1.0,
+            channels: vec![translation(&[], Interpolation::Linear), missing_node],
+        };
+        animation.apply(0.5, &mut nodes);
+        assert_eq!(nodes[0].translation, vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn apply_past_the_end_leaves_the_last_pose() {
+        let mut nodes = vec![Node::default()];
+        let animation = Animation {
+            name: "test".to_string(),
+            duration: 1.0,
+            channels: vec![
+                translation(&[(0.0, vec4(0.0, 0.0, 0.0, 0.0)), (1.0, vec4(1.0, 2.0, 3.0, 0.0))], Interpolation::Linear),
+                channel(Property::Scale, Interpolation::Step, &[(0.0, vec4(2.0, 2.0, 2.0, 0.0))]),
+            ],
+        };
+        animation.apply(7.0, &mut nodes);
+        assert_eq!(nodes[0].translation, vec3(1.0, 2.0, 3.0));
+        assert_eq!(nodes[0].scale, vec3(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn global_matrices_follow_parents() {
+        let parent = Node { translation: vec3(1.0, 0.0, 0.0), ..Node::default() };
+        let child = Node { parent: Some(1), translation: vec3(0.0, 2.0, 0.0), ..Node::default() };
+        let globals = global_matrices(&[child, parent]);
+        assert_eq!(globals[1], Matrix4::from_translation(vec3(1.0, 0.0, 0.0)));
+        assert_eq!(globals[0], Matrix4::from_translation(vec3(1.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn global_matrices_cut_parents_that_loop() {
+        let first = Node { parent: Some(1), translation: vec3(1.0, 0.0, 0.0), ..Node::default() };
+        let second = Node { parent: Some(0), translation: vec3(0.0, 2.0, 0.0), ..Node::default() };
+        let globals = global_matrices(&[first, second]);
+        assert_eq!(globals[1], Matrix4::from_translation(vec3(0.0, 2.0, 0.0)));
+        assert_eq!(globals[0], Matrix4::from_translation(vec3(1.0, 2.0, 0.0)));
+    }
+}
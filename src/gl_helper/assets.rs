@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use image::{DynamicImage, GenericImage};
+
+use crate::gl;
+use crate::gl_helper::model::{Model, Normals};
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::texture::{create_texture_rgba, load_cubemap, texture_from_image};
+
+/// A GL texture and about how much GPU memory it takes
+struct LoadedTexture {
+    texture: u32,
+    bytes: usize,
+}
+
+/// A compiled program and the name it is shown by in the report
+struct LoadedShader {
+    name: String,
+    program: Rc<ShaderProgram>,
+}
+
+/// Loads each model, texture and shader the first time it is asked for and hands out the same
+/// one after that. Models and shaders are reference counted so everything drawn with them shares
+/// one copy, textures are GL names which are cheap to copy anyway.
+pub struct Assets {
+    /// By path and normals, the same file with flat and smooth normals is two models
+    models: HashMap<(String, Normals), Rc<Model>>,
+    /// Files by path, textures made in code or read from inside a model by a name
+    textures: HashMap<String, LoadedTexture>,
+    cubemaps: HashMap<String, LoadedTexture>,
+    /// By vertex and fragment source, so a name can not hand back a program built from other sources
+    shaders: HashMap<(String, String), LoadedShader>,
+    /// Something has been loaded since the report was last taken
+    changed: bool,
+}
+
+impl Assets {
+    pub fn new() -> Assets {
+        Assets {
+            models: HashMap::new(),
+            textures: HashMap::new(),
+            cubemaps: HashMap::new(),
+            shaders: HashMap::new(),
+            changed: true,
+        }
+    }
+
+    /// An OBJ, glTF or GLB model with its textures, see Model::new
+    pub fn model(&mut self, gl: &gl::Gl, path: &str, normals: Normals) -> Result<Rc<Model>, String> {
+        let key = (path.to_string(), normals);
+        if let Some(model) = self.models.get(&key) {
+            return Ok(Rc::clone(model));
+        }
+        let model = Rc::new(Model::new(gl, self, path, normals)?);
+        self.models.insert(key, Rc::clone(&model));
+        self.changed = true;
+        Ok(model)
+    }
+
+    /// Texture from an image file in any format, converted to RGBA. A file that can not be
+    /// loaded is not remembered so asking again tries again.
+    pub fn texture(&mut self, gl: &gl::Gl, path: &str) -> Result<u32, String> {
+        if let Some(loaded) = self.textures.get(path) {
+            return Ok(loaded.texture);
+        }
+        let img = image::open(&Path::new(path)).map_err(|e| format!("{} {}", path, e))?;
+        Ok(self.image_texture(gl, path, &img))
+    }
+
+    /// Texture from an image already in memory, such as one embedded in a glTF file
+    pub fn image_texture(&mut self, gl: &gl::Gl, name: &str, img: &DynamicImage) -> u32 {
+        if let Some(loaded) = self.textures.get(name) {
+            return loaded.texture;
+        }
+        let texture = texture_from_image(gl, img);
+        let bytes = img.width() as usize * img.height() as usize * 4;
+        self.add_texture(name, texture, bytes);
+        texture
+    }
+
+    /// Texture from RGBA pixels made in code, pixels is only called the first time
+    pub fn rgba_texture<F: FnOnce() -> Vec<u8>>(&mut self, gl: &gl::Gl, name: &str, width: u32, height: u32, pixels: F) -> u32 {
+        if let Some(loaded) = self.textures.get(name) {
+            return loaded.texture;
+        }
+        let pixels = pixels();
+        let texture = create_texture_rgba(gl, width, height, &pixels);
+        self.add_texture(name, texture, pixels.len());
+        texture
+    }
+
+    /// A texture made and filled in somewhere else, such as a render target, so it is in the report
+    pub fn add_texture(&mut self, name: &str, texture: u32, bytes: usize) {
+        self.textures.insert(name.to_string(), LoadedTexture { texture, bytes });
+        self.changed = true;
+    }
+
+    /// One plain white texel, for things drawn with just a colour
+    pub fn white(&mut self, gl: &gl::Gl) -> u32 {
+        self.rgba_texture(gl, "white", 1, 1, || vec![255, 255, 255, 255])
+    }
+
+    /// Cube map from a directory of six faces, see load_cubemap
+    pub fn cubemap(&mut self, gl: &gl::Gl, directory: &str) -> Result<u32, String> {
+        if let Some(loaded) = self.cubemaps.get(directory) {
+            return Ok(loaded.texture);
+        }
+        let (texture, bytes) = load_cubemap(gl, directory)?;
+        self.cubemaps.insert(directory.to_string(), LoadedTexture { texture, bytes });
+        self.changed = true;
+        Ok(texture)
+    }
+
+    /// Programs are shared by everything built from the same sources, the name is for the report
+    pub fn shader(&mut self, gl: &gl::Gl, name: &str, vertex_shader_source: &str, fragment_shader_source: &str) -> Rc<ShaderProgram> {
+        let key = (vertex_shader_source.to_string(), fragment_shader_source.to_string());
+        let changed = &mut self.changed;
+        let loaded = self.shaders.entry(key).or_insert_with(|| {
+            *changed = true;
+            LoadedShader {
+                name: name.to_string(),
+                program: Rc::new(ShaderProgram::new(gl, vertex_shader_source, fragment_shader_source)),
+            }
+        });
+        Rc::clone(&loaded.program)
+    }
+
+    /// The report if anything has been loaded since it was last taken
+    pub fn take_report(&mut self) -> Option<Vec<String>> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+        Some(self.report())
+    }
+
+    /// A line for each asset with how much GPU memory it takes and how many hold it, then the totals
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut models: Vec<_> = self.models.iter().collect();
+        models.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+        for ((path, normals), model) in models {
+            lines.push(format!("model {} {:?}, {} meshes, {} KB, {} users",
+                               path, normals, model.sub_models.len(), model.mesh_bytes / 1024, Rc::strong_count(model) - 1));
+        }
+        let mut textures: Vec<_> = self.textures.iter().map(|(name, t)| ("texture", name, t))
+            .chain(self.cubemaps.iter().map(|(name, t)| ("cube map", name, t))).collect();
+        textures.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        for (kind, name, loaded) in &textures {
+            lines.push(format!("{} {}, {} KB", kind, name, loaded.bytes / 1024));
+        }
+        let mut shaders: Vec<_> = self.shaders.values().collect();
+        shaders.sort_by(|a, b| a.name.cmp(&b.name));
+        for LoadedShader { name, program } in shaders {
+            lines.push(format!("shader {}, {} uniforms, {} users", name, program.uniform_count(), Rc::strong_count(program) - 1));
+        }
+        let mesh_bytes: usize = self.models.values().map(|m| m.mesh_bytes).sum();
+        let texture_bytes: usize = textures.iter().map(|(_, _, t)| t.bytes).sum();
+        lines.push(format!("{} models {} KB, {} textures {} KB, {} shaders, {} KB in all",
+                           self.models.len(), mesh_bytes / 1024, textures.len(), texture_bytes / 1024,
+                           self.shaders.len(), (mesh_bytes + texture_bytes) / 1024));
+        lines
+    }
+}
@@ -0,0 +1,44 @@
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+
+use cgmath::{Matrix, Matrix4};
+
+use crate::gl;
+
+/// Name of the uniform block holding view and projection, shaders declare it as
+/// `layout (std140) uniform Camera { mat4 view; mat4 projection; };`
+pub const CAMERA_BLOCK: &str = "Camera";
+/// Uniform buffer binding point every program's Camera block is bound to
+pub const CAMERA_BINDING: u32 = 0;
+
+/// View and projection in one uniform buffer shared by all the programs, set once a frame
+/// instead of on every program for every object drawn
+pub struct CameraUniforms {
+    buffer: u32,
+}
+
+impl CameraUniforms {
+    pub fn new(gl: &gl::Gl) -> CameraUniforms {
+        let mut buffer: u32 = 0;
+        unsafe {
+            gl.GenBuffers(1, &mut buffer);
+            gl.BindBuffer(gl::UNIFORM_BUFFER, buffer);
+            gl.BufferData(gl::UNIFORM_BUFFER, (2 * size_of::<Matrix4<f32>>()) as isize, ptr::null(), gl::DYNAMIC_DRAW);
+            gl.BindBuffer(gl::UNIFORM_BUFFER, 0);
+            gl.BindBufferBase(gl::UNIFORM_BUFFER, CAMERA_BINDING, buffer);
+        }
+        CameraUniforms { buffer }
+    }
+
+    pub fn set(&self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let size = size_of::<Matrix4<f32>>() as isize;
+        unsafe {
+            gl.BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+            // std140 lays a mat4 out as four vec4 columns, the same as cgmath
+            gl.BufferSubData(gl::UNIFORM_BUFFER, 0, size, view.as_ptr() as *const c_void);
+            gl.BufferSubData(gl::UNIFORM_BUFFER, size, size, projection.as_ptr() as *const c_void);
+            gl.BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+
+use cgmath::{vec2, vec4, Quaternion, Vector3, Vector4, Zero};
+use gltf::Gltf;
+use gltf::animation::util::ReadOutputs;
+use gltf::mesh::Mode;
+use image::DynamicImage;
+
+use crate::gl_helper::animation::{Animation, Channel, Interpolation, Node, Property};
+use crate::gl_helper::model::{Material, MeshData, Vertex};
+
+/// A mesh and the image used for its base colour, if it has one
+pub struct GltfMesh {
+    pub data: MeshData,
+    pub image: Option<usize>,
+}
+
+/// Everything read from a .gltf or .glb file before it goes to the GPU
+pub struct GltfData {
+    pub meshes: Vec<GltfMesh>,
+    /// None for images that could not be decoded
+    pub images: Vec<Option<DynamicImage>>,
+    pub nodes: Vec<Node>,
+    pub animations: Vec<Animation>,
+}
+
+/// Reads the meshes of the default scene with their node hierarchy, base colour textures and
+/// node animations. Skins and morph targets are not supported, skinned meshes are drawn in
+/// their bind pose.
+pub fn load_gltf(path: &str) -> Result<GltfData, String> {
+    let gltf = Gltf::open(path).map_err(|e| format!("{} {}", path, e))?;
+    let directory = Path::new(path).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+
+    let mut buffers: Vec<Vec<u8>> = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or(format!("{} has no binary chunk", path))?,
+            gltf::buffer::Source::Uri(uri) => read_uri(&directory, uri).map_err(|e| format!("{} {}", path, e))?,
+        };
+        if data.len() < buffer.length() {
+            return Err(format!("{} buffer {} is {} bytes, expected {}", path, buffer.index(), data.len(), buffer.length()));
+        }
+        buffers.push(data);
+    }
+    let buffer_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|b| b.as_slice());
+
+    let mut nodes: Vec<Node> = gltf.nodes().map(|node| {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        Node {
+            parent: None,
+            translation: Vector3::from(translation),
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: Vector3::from(scale),
+        }
+    }).collect();
+    for node in gltf.nodes() {
+        for child in node.children() {
+            nodes[child.index()].parent = Some(node.index());
+        }
+    }
+
+    let mut meshes = Vec::new();
+    for node in scene_nodes(&gltf) {
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        if node.skin().is_some() {
+            println!("{} node {} is skinned, drawing it in its bind pose", path, node.index());
+        }
+        for primitive in mesh.primitives() {
+            let name = format!("{}/{}", mesh.name().unwrap_or("mesh"), primitive.index());
+            if primitive.mode() != Mode::Triangles {
+                println!("{} {} is not triangles, skipping it", path, name);
+                continue;
+            }
+            let reader = primitive.reader(buffer_data);
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(positions) => positions.collect(),
+                None => {
+                    println!("{} {} has no positions, skipping it", path, name);
+                    continue;
+                }
+            };
+            let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+            let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+            if uvs.is_none() {
+                println!("{} {} has no texture coordinates", path, name);
+            }
+            if let Some(count) = normals.as_ref().map(|n| n.len()).filter(|n| *n != positions.len()) {
+                return Err(format!("{} {} has {} normals for {} positions", path, name, count, positions.len()));
+            }
+            if let Some(count) = uvs.as_ref().map(|t| t.len()).filter(|t| *t != positions.len()) {
+                return Err(format!("{} {} has {} texture coordinates for {} positions", path, name, count, positions.len()));
+            }
+            let vertices = positions.iter().enumerate().map(|(i, p)| Vertex {
+                position: Vector3::from(*p),
+                normal: normals.as_ref().map(|n| Vector3::from(n[i])).unwrap_or(Vector3::zero()),
+                // glTF textures start at the top, ours are flipped to start at the bottom
+                tex_coords: uvs.as_ref().map(|t| vec2(t[i][0], 1.0 - t[i][1])).unwrap_or(vec2(0.0, 0.0)),
+            }).collect();
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            if let Some(bad) = indices.iter().find(|i| **i as usize >= positions.len()) {
+                return Err(format!("{} {} index {} is past the last vertex", path, name, bad));
+            }
+
+            let pbr = primitive.material().pbr_metallic_roughness();
+            let data = MeshData {
+                name,
+                node: node.index(),
+                vertices,
+                indices,
+                has_normals: normals.is_some(),
+                material: Material::from_pbr(pbr.base_color_factor(), pbr.metallic_factor(), pbr.roughness_factor()),
+            };
+            let image = pbr.base_color_texture().map(|info| info.texture().source().index());
+            meshes.push(GltfMesh { data, image });
+        }
+    }
+
+    let images = gltf.images().map(|image| {
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &buffers[view.buffer().index()];
+                Ok(buffer[view.offset()..view.offset() + view.length()].to_vec())
+            }
+            gltf::image::Source::Uri { uri, .. } => read_uri(&directory, uri),
+        };
+        match bytes.and_then(|b| image::load_from_memory(&b).map_err(|e| e.to_string())) {
+            Ok(decoded) => Some(decoded),
+            Err(e) => {
+                println!("{} unable to load image {} {}", path, image.index(), e);
+                None
+            }
+        }
+    }).collect();
+
+    let mut animations = Vec::new();
+    for animation in gltf.animations() {
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let reader = channel.reader(buffer_data);
+            let times: Vec<f32> = match reader.read_inputs() {
+                Some(inputs) => inputs.collect(),
+                None => continue,
+            };
+            let (property, mut values): (Property, Vec<Vector4<f32>>) = match reader.read_outputs() {
+                Some(ReadOutputs::Translations(t)) => (Property::Translation, t.map(|v| vec4(v[0], v[1], v[2], 0.0)).collect()),
+                Some(ReadOutputs::Rotations(r)) => (Property::Rotation, r.into_f32().map(Vector4::from).collect()),
+                Some(ReadOutputs::Scales(s)) => (Property::Scale, s.map(|v| vec4(v[0], v[1], v[2], 0.0)).collect()),
+                _ => continue,
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => {
+                    // in tangent, value, out tangent for each key, the tangents are dropped
+                    values = values.chunks(3).filter(|c| c.len() == 3).map(|c| c[1]).collect();
+                    Interpolation::Linear
+                }
+            };
+            if times.is_empty() || times.len() != values.len() {
+                println!("{} animation {} channel has {} times and {} values, skipping it", path, animation.index(), times.len(), values.len());
+                continue;
+            }
+            channels.push(Channel {
+                node: channel.target().node().index(),
+                property,
+                interpolation,
+                times,
+                values,
+            });
+        }
+        let duration = channels.iter().filter_map(|c| c.times.last()).fold(0.0, |a: f32, b| a.max(*b));
+        animations.push(Animation {
+            name: animation.name().map(|n| n.to_string()).unwrap_or(format!("animation{}", animation.index())),
+            duration,
+            channels,
+        });
+    }
+
+    Ok(GltfData { meshes, images, nodes, animations })
+}
+
+/// Nodes of the default scene, or the first scene, and all of their children.
+/// Files without scenes have every node drawn.
+fn scene_nodes(gltf: &Gltf) -> Vec<gltf::Node<'_>> {
+    let scene = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene,
+        None => return gltf.nodes().collect(),
+    };
+    let mut found = Vec::new();
+    let mut to_visit: Vec<gltf::Node> = scene.nodes().collect();
+    while let Some(node) = to_visit.pop() {
+        to_visit.extend(node.children());
+        found.push(node);
+    }
+    found
+}
+
+/// Files are relative to the glTF file, data URIs carry the bytes in base64
+fn read_uri(directory: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        let at = uri.find(";base64,").ok_or(format!("data uri without base64 {}", &uri[..uri.len().min(40)]))?;
+        return base64::decode(&uri[at + 8..]).map_err(|e| format!("data uri {}", e));
+    }
+    // file names in URIs have anything other than plain characters escaped as %XX bytes of UTF-8
+    let name = urlencoding::decode(uri).map_err(|_| format!("uri is not UTF-8 {}", uri))?;
+    let file = directory.join(name.as_ref());
+    fs::read(&file).map_err(|e| format!("{} {}", file.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn data_uris_are_decoded_without_a_file() {
+        let bytes = read_uri(Path::new("no-such-directory"), "data:application/octet-stream;base64,AAEC").unwrap();
+        assert_eq!(bytes, vec![0, 1, 2]);
+        assert!(read_uri(Path::new("no-such-directory"), "data:text/plain,hello").is_err());
+        assert!(read_uri(Path::new("no-such-directory"), "data:application/octet-stream;base64,TW*u").is_err());
+    }
+
+    #[test]
+    fn file_uris_are_unescaped_and_relative_to_the_model() {
+        let directory = env::temp_dir().join(format!("rust-invaders-gltf-uri-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("alien body.bin"), [7u8, 8, 9]).unwrap();
+        assert_eq!(read_uri(&directory, "alien%20body.bin").unwrap(), vec![7, 8, 9]);
+        assert!(read_uri(&directory, "%FF.bin").is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
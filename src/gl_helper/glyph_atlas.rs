@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::raw::c_void;
+
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont, point};
+use cgmath::{vec4, Vector4};
+
+use crate::gl;
+use crate::gl_helper::assets::Assets;
+
+const ATLAS_SIZE: usize = 1024;
+/// Gap left between glyphs so linear filtering does not bleed in the neighbours
+const PADDING: usize = 1;
+
+#[derive(Clone, Copy)]
+pub struct GlyphInfo {
+    /// x, y, width, height in texture coordinates, y is flipped to match the other textures
+    pub uv_rect: Vector4<f32>,
+    /// top left of the bitmap from the pen position on the baseline, in pixels
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// TrueType font rasterised into one texture as glyphs are needed. Glyphs are cached per pixel
+/// size and packed in rows, the rows that changed are uploaded again before drawing.
+pub struct GlyphAtlas {
+    pub font: FontVec,
+    pub texture: u32,
+    pixels: Vec<u8>,
+    cursor_x: usize,
+    cursor_y: usize,
+    row_height: usize,
+    glyphs: HashMap<(GlyphId, u32), Option<GlyphInfo>>,
+    /// First and one past the last row of the texture changed since the last upload
+    dirty_rows: Option<(usize, usize)>,
+    /// Times the atlas has filled up and been cleared
+    clears: usize,
+}
+
+impl GlyphAtlas {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, font_file: &str) -> Result<GlyphAtlas, String> {
+        let data = fs::read(font_file).map_err(|e| format!("{} {}", font_file, e))?;
+        let font = FontVec::try_from_vec(data).map_err(|e| format!("{} {}", font_file, e))?;
+
+        let mut texture: u32 = 0;
+        unsafe {
+            gl.GenTextures(1, &mut texture);
+            gl.BindTexture(gl::TEXTURE_2D, texture);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            // storage only, upload fills it in
+            gl.TexImage2D(gl::TEXTURE_2D,
+                          0,
+                          gl::RGBA as i32,
+                          ATLAS_SIZE as i32,
+                          ATLAS_SIZE as i32,
+                          0,
+                          gl::RGBA,
+                          gl::UNSIGNED_BYTE,
+                          std::ptr::null());
+        }
+        assets.add_texture(&format!("glyph atlas {}", font_file), texture, ATLAS_SIZE * ATLAS_SIZE * 4);
+
+        let mut atlas = GlyphAtlas {
+            font,
+            texture,
+            pixels: Vec::new(),
+            cursor_x: 0,
+            cursor_y: 0,
+            row_height: 0,
+            glyphs: HashMap::new(),
+            dirty_rows: None,
+            clears: 0,
+        };
+        atlas.clear();
+        Ok(atlas)
+    }
+
+    /// Throws away every cached glyph, used when the texture fills up
+    fn clear(&mut self) {
+        self.pixels = vec![0; ATLAS_SIZE * ATLAS_SIZE * 4];
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel[0] = 255;
+            pixel[1] = 255;
+            pixel[2] = 255;
+        }
+        self.cursor_x = PADDING;
+        self.cursor_y = PADDING;
+        self.row_height = 0;
+        self.glyphs.clear();
+        self.dirty_rows = Some((0, ATLAS_SIZE));
+        self.clears = self.clears + 1;
+    }
+
+    /// Position and texture coordinates of a glyph, None for glyphs with nothing to draw such as a space
+    pub fn glyph(&mut self, id: GlyphId, px_size: u32) -> Option<GlyphInfo> {
+        if let Some(info) = self.glyphs.get(&(id, px_size)) {
+            return *info;
+        }
+        let info = self.rasterise(id, px_size);
+        self.glyphs.insert((id, px_size), info);
+        info
+    }
+
+    fn rasterise(&mut self, id: GlyphId, px_size: u32) -> Option<GlyphInfo> {
+        let glyph = id.with_scale_and_position(PxScale::from(px_size as f32), point(0.0, 0.0));
+        let outline = self.font.outline_glyph(glyph)?;
+        let bounds = outline.px_bounds();
+        let (width, height) = (bounds.width() as usize, bounds.height() as usize);
+        if width == 0 || height == 0 || width + PADDING * 2 > ATLAS_SIZE || height + PADDING * 2 > ATLAS_SIZE {
+            return None;
+        }
+
+        if self.cursor_x + width + PADDING > ATLAS_SIZE {
+            self.cursor_x = PADDING;
+            self.cursor_y = self.cursor_y + self.row_height + PADDING;
+            self.row_height = 0;
+        }
+        if self.cursor_y + height + PADDING > ATLAS_SIZE {
+            println!("glyph atlas full, starting again");
+            self.clear();
+        }
+
+        let (x0, y0) = (self.cursor_x, self.cursor_y);
+        let pixels = &mut self.pixels;
+        outline.draw(|x, y, coverage| {
+            let (x, y) = (x0 + x as usize, y0 + y as usize);
+            if x < ATLAS_SIZE && y < ATLAS_SIZE {
+                // rows are stored bottom up like the other textures
+                let row = ATLAS_SIZE - 1 - y;
+                pixels[(row * ATLAS_SIZE + x) * 4 + 3] = (coverage.min(1.0) * 255.0) as u8;
+            }
+        });
+        self.cursor_x = self.cursor_x + width + PADDING;
+        self.row_height = self.row_height.max(height);
+        let (first, last) = (ATLAS_SIZE - (y0 + height), ATLAS_SIZE - y0);
+        self.dirty_rows = Some(match self.dirty_rows {
+            Some((start, end)) => (start.min(first), end.max(last)),
+            None => (first, last),
+        });
+
+        let size = ATLAS_SIZE as f32;
+        Some(GlyphInfo {
+            uv_rect: vec4(x0 as f32 / size,
+                          1.0 - (y0 + height) as f32 / size,
+                          width as f32 / size,
+                          height as f32 / size),
+            left: bounds.min.x,
+            top: bounds.min.y,
+            width: width as f32,
+            height: height as f32,
+        })
+    }
+
+    /// Sends the rows of the texture that glyphs have been added to since the last upload
+    pub fn upload(&mut self, gl: &gl::Gl) {
+        let (start, end) = match self.dirty_rows.take() {
+            Some(rows) => rows,
+            None => return,
+        };
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, self.texture);
+            gl.TexSubImage2D(gl::TEXTURE_2D,
+                             0,
+                             0,
+                             start as i32,
+                             ATLAS_SIZE as i32,
+                             (end - start) as i32,
+                             gl::RGBA,
+                             gl::UNSIGNED_BYTE,
+                             &self.pixels[start * ATLAS_SIZE * 4] as *const u8 as *const c_void);
+        }
+    }
+
+    /// Glyphs and pen positions for a line of text, all in the texture together. If the atlas
+    /// fills up part way through, the glyphs already placed were thrown away so the line is
+    /// placed again in the emptied atlas.
+    pub fn place(&mut self, text: &str, px_size: u32) -> Vec<(GlyphInfo, f32)> {
+        let clears = self.clears;
+        let placed = self.place_once(text, px_size);
+        if self.clears == clears {
+            return placed;
+        }
+        self.place_once(text, px_size)
+    }
+
+    fn place_once(&mut self, text: &str, px_size: u32) -> Vec<(GlyphInfo, f32)> {
+        self.layout(text, px_size).into_iter()
+            .filter_map(|(id, pen)| self.glyph(id, px_size).map(|info| (info, pen)))
+            .collect()
+    }
+
+    pub fn ascent(&self, px_size: u32) -> f32 {
+        self.font.as_scaled(PxScale::from(px_size as f32)).ascent()
+    }
+
+    pub fn line_height(&self, px_size: u32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(px_size as f32));
+        scaled.height() + scaled.line_gap()
+    }
+
+    /// Pen movement for each character of the text including kerning with the character before it
+    pub fn layout(&self, text: &str, px_size: u32) -> Vec<(GlyphId, f32)> {
+        let scaled = self.font.as_scaled(PxScale::from(px_size as f32));
+        let mut pen = 0.0;
+        let mut previous: Option<GlyphId> = None;
+        let mut placed = Vec::new();
+        for c in text.chars() {
+            let id = self.font.glyph_id(c);
+            if let Some(previous) = previous {
+                pen = pen + scaled.kern(previous, id);
+            }
+            placed.push((id, pen));
+            pen = pen + scaled.h_advance(id);
+            previous = Some(id);
+        }
+        placed
+    }
+
+    pub fn text_width(&self, text: &str, px_size: u32) -> f32 {
+        let scaled = self.font.as_scaled(PxScale::from(px_size as f32));
+        match self.layout(text, px_size).last() {
+            Some((id, pen)) => pen + scaled.h_advance(*id),
+            None => 0.0,
+        }
+    }
+}
@@ -1,50 +1,129 @@
+use std::rc::Rc;
+
 use crate::gl_helper::model::Model;
-use cgmath::{Matrix4, vec3};
-use crate::gl_helper::gl_matrix4;
-use std::ptr;
+use cgmath::{Matrix4, Vector4, vec3};
+use crate::gl_helper::instancing::{draw_elements_instanced, upload_instances, InstanceData, WHITE_TINT};
+use crate::gl_helper::shadow::ShadowMap;
+use crate::gl_helper::shader::ShaderProgram;
 
 use crate::gl;
 use crate::game::Render;
 
+/// Cheap to clone, the model is shared
 #[derive(Clone)]
 pub struct ModelInstance {
-    model: Model,
+    model: Rc<Model>,
     pub(crate) matrix: Matrix4<f32>,
     pub(crate) scale: f32,
+    /// Animation of the model being played, None for the bind pose
+    pub(crate) animation: Option<usize>,
+    pub(crate) animation_time: f32,
+    looping: bool,
+    /// Multiplies the colour of the model
+    pub(crate) tint: Vector4<f32>,
 }
 
 
 impl ModelInstance {
-    pub fn new(model:Model, scale: f32) -> ModelInstance {
+    pub fn new(model: Rc<Model>, scale: f32) -> ModelInstance {
         ModelInstance {
             model,
             matrix: Matrix4::from_translation(vec3(0.0,0.0,0.0)),
             //position,
             scale,
+            animation: None,
+            animation_time: 0.0,
+            looping: false,
+            tint: WHITE_TINT,
         }
     }
-}
 
-impl Render for ModelInstance {
-    fn render(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+    /// Plays one of the model's animations from the start, None goes back to the bind pose
+    pub fn play(&mut self, animation: Option<usize>, looping: bool) {
+        self.animation = animation;
+        self.animation_time = 0.0;
+        self.looping = looping;
+    }
+
+    /// Moves the animation on, true once one that does not loop has reached its end
+    pub fn advance(&mut self, seconds: f32) -> bool {
+        let duration = match self.animation.and_then(|a| self.model.animations.get(a)) {
+            Some(animation) => animation.duration,
+            None => return false,
+        };
+        self.animation_time = self.animation_time + seconds;
+        if self.looping {
+            if duration > 0.0 {
+                self.animation_time = self.animation_time % duration;
+            }
+            return false;
+        }
+        self.animation_time >= duration
+    }
 
-        //self.matrix = self.matrix * Matrix4::<f32>::from_angle_y(Deg(1.0));
+    /// Transform and tint of each of the model's meshes in the current pose
+    fn instance_data(&self) -> Vec<InstanceData> {
         let matrix = self.matrix * Matrix4::from_scale(self.scale);
+        let pose = self.model.pose(self.animation, self.animation_time);
+        self.model.sub_models.iter().map(|sub_model| InstanceData {
+            transform: matrix * pose[sub_model.node],
+            tint: self.tint,
+        }).collect()
+    }
 
-        for sub_model in &self.model.sub_models {
+    /// Sends the instances of one mesh of the model, all of them are of the same model
+    fn upload(gl: &gl::Gl, model: &Model, per_instance: &[Vec<InstanceData>], sub_model: usize) -> usize {
+        let instances: Vec<InstanceData> = per_instance.iter().map(|meshes| meshes[sub_model]).collect();
+        upload_instances(gl, model.instance_buffer, &instances);
+        instances.len()
+    }
+
+    /// Draws every instance into the shadow map in its current pose, a draw call for each mesh
+    pub fn render_shadows(gl: &gl::Gl, instances: &[&ModelInstance], shadow: &ShadowMap) {
+        let model = match instances.first() {
+            Some(first) => &first.model,
+            None => return,
+        };
+        let per_instance: Vec<Vec<InstanceData>> = instances.iter().map(|i| i.instance_data()).collect();
+        for (i, sub_model) in model.sub_models.iter().enumerate() {
+            let count = ModelInstance::upload(gl, model, &per_instance, i);
+            shadow.cast_elements(gl, sub_model.vao, sub_model.indices_len, count);
+        }
+    }
+
+    /// Draws every instance with a draw call for each mesh rather than one for each mesh of each instance.
+    /// They must all be instances of the same model.
+    pub fn render_all(gl: &gl::Gl, instances: &[&ModelInstance]) {
+        let model = match instances.first() {
+            Some(first) => &first.model,
+            None => return,
+        };
+        debug_assert!(instances.iter().all(|i| Rc::ptr_eq(&i.model, model)));
+        let per_instance: Vec<Vec<InstanceData>> = instances.iter().map(|i| i.instance_data()).collect();
+        let shader = &model.our_shader;
+        shader.use_program(gl);
+        for (i, sub_model) in model.sub_models.iter().enumerate() {
+            let count = ModelInstance::upload(gl, model, &per_instance, i);
             unsafe {
-                gl.UseProgram(self.model.our_shader);
                 gl.ActiveTexture(gl::TEXTURE0);
                 gl.BindTexture(gl::TEXTURE_2D, sub_model.texture);
-                gl.BindVertexArray(sub_model.vao);
-
-                gl_matrix4(gl, self.model.our_shader, matrix, "transform");
-                gl_matrix4(gl, self.model.our_shader, *view, "view");
-                gl_matrix4(gl, self.model.our_shader, *projection, "projection");
-
-                gl.DrawElements(gl::TRIANGLES, sub_model.indices_len as i32, gl::UNSIGNED_INT, ptr::null());
-                gl.BindVertexArray(0);
             }
+            shader.set_vector3(gl, "materialAmbient", sub_model.material.ambient);
+            shader.set_vector3(gl, "materialDiffuse", sub_model.material.diffuse);
+            shader.set_vector3(gl, "materialSpecular", sub_model.material.specular);
+            shader.set_float(gl, "materialShininess", sub_model.material.shininess);
+            draw_elements_instanced(gl, sub_model.vao, sub_model.indices_len, count);
         }
     }
-}
\ No newline at end of file
+
+    /// Shader program of the model, for setting the lighting
+    pub fn shader(&self) -> &ShaderProgram {
+        &self.model.our_shader
+    }
+}
+
+impl Render for ModelInstance {
+    fn render(&mut self, gl: &gl::Gl) {
+        ModelInstance::render_all(gl, &[self]);
+    }
+}
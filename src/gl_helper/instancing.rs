@@ -0,0 +1,109 @@
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cgmath::{Matrix4, Vector4};
+
+use crate::gl;
+
+/// The instance transform is a mat4 so it takes this location and the three after it
+pub const INSTANCE_TRANSFORM_LOCATION: u32 = 3;
+pub const INSTANCE_TINT_LOCATION: u32 = 7;
+
+pub const WHITE_TINT: Vector4<f32> = Vector4 { x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
+
+static DRAW_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+/// What changes from one copy of a mesh to the next in an instanced draw
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InstanceData {
+    pub transform: Matrix4<f32>,
+    /// Multiplies the colour, alpha included
+    pub tint: Vector4<f32>,
+}
+
+impl InstanceData {
+    pub fn new(transform: Matrix4<f32>) -> InstanceData {
+        InstanceData { transform, tint: WHITE_TINT }
+    }
+}
+
+/// An empty buffer for InstanceData
+pub fn create_instance_buffer(gl: &gl::Gl) -> u32 {
+    let mut buffer: u32 = 0;
+    unsafe {
+        gl.GenBuffers(1, &mut buffer);
+    }
+    buffer
+}
+
+/// Reads the instance attributes of the VAO from the buffer. Several VAOs can share one buffer
+/// as long as each draw uploads its own instances first.
+pub fn add_instance_attributes(gl: &gl::Gl, vao: u32, buffer: u32) {
+    let stride = size_of::<InstanceData>() as i32;
+    let column = size_of::<Vector4<f32>>();
+    unsafe {
+        gl.BindVertexArray(vao);
+        gl.BindBuffer(gl::ARRAY_BUFFER, buffer);
+        for i in 0..4 {
+            let location = INSTANCE_TRANSFORM_LOCATION + i;
+            gl.EnableVertexAttribArray(location);
+            gl.VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, stride, (i as usize * column) as *const c_void);
+            gl.VertexAttribDivisor(location, 1);
+        }
+        gl.EnableVertexAttribArray(INSTANCE_TINT_LOCATION);
+        gl.VertexAttribPointer(INSTANCE_TINT_LOCATION, 4, gl::FLOAT, gl::FALSE, stride, (4 * column) as *const c_void);
+        gl.VertexAttribDivisor(INSTANCE_TINT_LOCATION, 1);
+        gl.BindVertexArray(0);
+    }
+}
+
+/// Replaces what is in the buffer, the old contents are orphaned so a draw still using them is not held up
+pub fn upload_instances(gl: &gl::Gl, buffer: u32, instances: &[InstanceData]) {
+    if instances.is_empty() {
+        return;
+    }
+    unsafe {
+        gl.BindBuffer(gl::ARRAY_BUFFER, buffer);
+        let size = (instances.len() * size_of::<InstanceData>()) as isize;
+        gl.BufferData(gl::ARRAY_BUFFER, size, ptr::null(), gl::STREAM_DRAW);
+        gl.BufferData(gl::ARRAY_BUFFER, size, &instances[0] as *const InstanceData as *const c_void, gl::STREAM_DRAW);
+        gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+}
+
+pub fn draw_elements_instanced(gl: &gl::Gl, vao: u32, count: usize, instances: usize) {
+    if instances == 0 {
+        return;
+    }
+    count_draw_call();
+    unsafe {
+        gl.BindVertexArray(vao);
+        gl.DrawElementsInstanced(gl::TRIANGLES, count as i32, gl::UNSIGNED_INT, ptr::null(), instances as i32);
+        gl.BindVertexArray(0);
+    }
+}
+
+pub fn draw_arrays_instanced(gl: &gl::Gl, vao: u32, count: usize, instances: usize) {
+    if instances == 0 {
+        return;
+    }
+    count_draw_call();
+    unsafe {
+        gl.BindVertexArray(vao);
+        gl.DrawArraysInstanced(gl::TRIANGLES, 0, count as i32, instances as i32);
+        gl.BindVertexArray(0);
+    }
+}
+
+/// Every draw call goes through here so a frame's total can be shown
+pub fn count_draw_call() {
+    DRAW_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Draw calls since the last time this was called
+pub fn take_draw_calls() -> usize {
+    DRAW_CALLS.swap(0, Ordering::Relaxed)
+}
@@ -0,0 +1,72 @@
+use cgmath::{vec3, InnerSpace, Vector3};
+
+use crate::gl;
+use crate::gl_helper::shader::ShaderProgram;
+
+/// Most point lights the model shader takes, the rest are ignored
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+const POINT_LIGHT_POSITION: [&str; MAX_POINT_LIGHTS] = ["pointLightPosition[0]", "pointLightPosition[1]", "pointLightPosition[2]", "pointLightPosition[3]"];
+const POINT_LIGHT_COLOUR: [&str; MAX_POINT_LIGHTS] = ["pointLightColour[0]", "pointLightColour[1]", "pointLightColour[2]", "pointLightColour[3]"];
+const POINT_LIGHT_RADIUS: [&str; MAX_POINT_LIGHTS] = ["pointLightRadius[0]", "pointLightRadius[1]", "pointLightRadius[2]", "pointLightRadius[3]"];
+
+#[derive(Clone, Copy)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub colour: Vector3<f32>,
+    /// Distance at which the light has faded to nothing
+    pub radius: f32,
+}
+
+/// Lights for the model shader, a directional sun plus a few point lights. The uniforms stay
+/// set on a shader program so this is applied once a frame rather than for every model.
+pub struct Lighting {
+    /// Direction the sunlight travels in
+    pub sun_direction: Vector3<f32>,
+    pub sun_colour: Vector3<f32>,
+    pub ambient: Vector3<f32>,
+    pub point_lights: Vec<PointLight>,
+    pub eye: Vector3<f32>,
+}
+
+impl Lighting {
+    pub fn new() -> Lighting {
+        Lighting {
+            sun_direction: vec3(-0.4, -1.0, -0.3).normalize(),
+            sun_colour: vec3(1.0, 0.95, 0.85),
+            ambient: vec3(1.0, 1.0, 1.0),
+            point_lights: Vec::new(),
+            eye: vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Keeps the point lights closest to the eye if there are more than the shader can take
+    pub fn set_point_lights(&mut self, mut lights: Vec<PointLight>) {
+        let eye = self.eye;
+        lights.sort_by(|a, b| (a.position - eye).magnitude2().partial_cmp(&(b.position - eye).magnitude2()).unwrap());
+        lights.truncate(MAX_POINT_LIGHTS);
+        self.point_lights = lights;
+    }
+
+    /// Sets the lights on each program, a program shared by several models is only set once
+    pub fn apply(&self, gl: &gl::Gl, programs: &[&ShaderProgram]) {
+        let mut done: Vec<u32> = Vec::new();
+        for program in programs {
+            if done.contains(&program.id) {
+                continue;
+            }
+            done.push(program.id);
+            program.use_program(gl);
+            program.set_vector3(gl, "sunDirection", self.sun_direction);
+            program.set_vector3(gl, "sunColour", self.sun_colour);
+            program.set_vector3(gl, "ambientColour", self.ambient);
+            program.set_vector3(gl, "eyePosition", self.eye);
+            program.set_int(gl, "pointLightCount", self.point_lights.len() as i32);
+            for (i, light) in self.point_lights.iter().enumerate() {
+                program.set_vector3(gl, POINT_LIGHT_POSITION[i], light.position);
+                program.set_vector3(gl, POINT_LIGHT_COLOUR[i], light.colour);
+                program.set_float(gl, POINT_LIGHT_RADIUS[i], light.radius);
+            }
+        }
+    }
+}
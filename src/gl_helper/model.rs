@@ -4,19 +4,79 @@ use std::ptr;
 
 
 
-use cgmath::{vec2, vec3, Vector3, Vector2};
+use cgmath::{vec2, vec3, Matrix4, Vector3, Vector2, InnerSpace, Zero};
 
 use crate::gl;
-use crate::gl_helper::texture::{create_texture };
+use crate::gl_helper::animation::{global_matrices, Animation, Node};
+use crate::gl_helper::gltf_model::{load_gltf, GltfData};
+use crate::gl_helper::assets::Assets;
 use std::mem::size_of;
-use crate::gl_helper::shader::create_shader;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::instancing::{add_instance_attributes, create_instance_buffer};
+
+/// Poses kept before the cache is emptied, animation times move on every frame so old ones go stale
+const POSE_CACHE_SIZE: usize = 128;
+
+/// Matrix of every node of a model, shared rather than copied for each instance
+pub type Pose = Rc<Vec<Matrix4<f32>>>;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
     pub tex_coords: Vector2<f32>,
 }
 
+/// Colours from the MTL file, the diffuse colour is multiplied by the texture
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+    pub shininess: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            ambient: vec3(0.2, 0.2, 0.2),
+            diffuse: vec3(0.8, 0.8, 0.8),
+            specular: vec3(0.0, 0.0, 0.0),
+            shininess: 1.0,
+        }
+    }
+}
+
+impl Material {
+    fn from_mtl(material: &tobj::Material) -> Material {
+        Material {
+            ambient: vec3(material.ambient[0], material.ambient[1], material.ambient[2]),
+            diffuse: vec3(material.diffuse[0], material.diffuse[1], material.diffuse[2]),
+            specular: vec3(material.specular[0], material.specular[1], material.specular[2]),
+            // Ns 0 is common in exported files, pow(x, 0) would light everything
+            shininess: material.shininess.max(1.0),
+        }
+    }
+
+    /// glTF metallic-roughness in Blinn-Phong terms. Rough surfaces get a dull, wide highlight
+    /// and metals take the highlight colour from their base colour.
+    pub fn from_pbr(base_colour: [f32; 4], metallic: f32, roughness: f32) -> Material {
+        let diffuse = vec3(base_colour[0], base_colour[1], base_colour[2]);
+        let highlight = vec3(1.0, 1.0, 1.0) * (1.0 - metallic) + diffuse * metallic;
+        Material {
+            ambient: diffuse * 0.25,
+            diffuse: diffuse * (1.0 - metallic * 0.5),
+            specular: highlight * (1.0 - roughness) * 0.5,
+            shininess: (2.0 / roughness.powi(4).max(0.0001) - 2.0).max(1.0).min(256.0),
+        }
+    }
+}
+
 /// Get offset to struct member, similar to `offset_of` in C/C++
 /// From https://stackoverflow.com/questions/40310483/how-to-get-pointer-offset-in-bytes/40310851#40310851
 macro_rules! offset_of {
@@ -31,92 +91,378 @@ precision mediump float;
 out vec4 FragColor;
 
 in vec2 TexCoord;
-in vec3 Pos;
+in vec3 Normal;
+in vec3 FragPos;
+in vec4 Tint;
 
 uniform sampler2D theTexture;
 
+uniform vec3 materialAmbient;
+uniform vec3 materialDiffuse;
+uniform vec3 materialSpecular;
+uniform float materialShininess;
+
+uniform vec3 sunDirection;
+uniform vec3 sunColour;
+uniform vec3 ambientColour;
+uniform vec3 eyePosition;
+
+#define MAX_POINT_LIGHTS 4
+uniform int pointLightCount;
+uniform vec3 pointLightPosition[MAX_POINT_LIGHTS];
+uniform vec3 pointLightColour[MAX_POINT_LIGHTS];
+uniform float pointLightRadius[MAX_POINT_LIGHTS];
+
+// Blinn-Phong, returns diffuse and specular light for one light
+void blinnPhong(vec3 normal, vec3 toEye, vec3 toLight, vec3 colour, inout vec3 diffuse, inout vec3 specular)
+{
+    diffuse += max(dot(normal, toLight), 0.0) * colour;
+    vec3 halfway = normalize(toLight + toEye);
+    specular += pow(max(dot(normal, halfway), 0.0), materialShininess) * colour;
+}
+
 void main()
 {
-	FragColor = texture(theTexture, TexCoord);
+    vec4 texel = texture(theTexture, TexCoord);
+    vec3 normal = normalize(Normal);
+    vec3 toEye = normalize(eyePosition - FragPos);
+
+    vec3 diffuse = vec3(0.0);
+    vec3 specular = vec3(0.0);
+    blinnPhong(normal, toEye, -sunDirection, sunColour, diffuse, specular);
+    for (int i = 0; i < MAX_POINT_LIGHTS; i++) {
+        if (i >= pointLightCount) {
+            break;
+        }
+        vec3 toLight = pointLightPosition[i] - FragPos;
+        float fade = clamp(1.0 - length(toLight) / pointLightRadius[i], 0.0, 1.0);
+        blinnPhong(normal, toEye, normalize(toLight), pointLightColour[i] * fade * fade, diffuse, specular);
+    }
 
+    vec3 colour = texel.rgb * (materialAmbient * ambientColour + materialDiffuse * diffuse) + materialSpecular * specular;
+	FragColor = vec4(colour, texel.a) * Tint;
 }
 ";
 const IMAGE_VERTEX_SHADER_SOURCE: &str = "#version 300 es
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec2 aTexCoord;
+layout (location = 2) in vec3 aNormal;
+layout (location = 3) in mat4 aTransform;
+layout (location = 7) in vec4 aTint;
 
 out vec2 TexCoord;
+out vec3 Normal;
+out vec3 FragPos;
+out vec4 Tint;
 
-uniform mat4 transform;
-uniform mat4 projection;
-uniform mat4 view;
+layout (std140) uniform Camera {
+	mat4 view;
+	mat4 projection;
+};
 
 void main()
 {
     TexCoord = vec2(aTexCoord.x  ,aTexCoord.y );
-	gl_Position = projection * view * transform * vec4(aPos ,1.0) ;
+    vec4 worldPos = aTransform * vec4(aPos, 1.0);
+    FragPos = worldPos.xyz;
+    Normal = mat3(transpose(inverse(aTransform))) * aNormal;
+    Tint = aTint;
+	gl_Position = projection * view * worldPos;
 }
 ";
 
 #[derive(Clone)]
 pub struct SubModel {
     pub(crate) texture: u32,
+    pub(crate) material: Material,
     pub indices_len: usize,
     pub(crate) vao: u32,
+    /// Node the mesh hangs off, its transform is applied before the model's own
+    pub(crate) node: usize,
 }
 
-#[derive(Clone)]
+/// How to make normals for a mesh that has none
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Normals {
+    /// Averaged across the faces that share a vertex, for rounded shapes
+    Smooth,
+    /// Every face gets its own, for hard edged shapes
+    Flat,
+}
+
+/// A mesh as read from a file, before it is sent to the GPU
+pub struct MeshData {
+    pub name: String,
+    pub node: usize,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub has_normals: bool,
+    pub material: Material,
+}
+
+/// Get models from Assets so each file is loaded once and shared by everything drawn with it
 pub struct Model {
-    pub(crate) our_shader: u32,
+    pub(crate) our_shader: Rc<ShaderProgram>,
     pub(crate) sub_models: Vec<SubModel>,
-
+    /// Distance from the model's origin to its furthest vertex, a sphere this size centred where
+    /// the model is placed holds all of it
+    pub bounding_radius: f32,
+    /// OBJ files have a single node that does nothing
+    pub(crate) nodes: Vec<Node>,
+    pub animations: Vec<Animation>,
+    /// Node matrices when nothing is animating
+    bind_pose: Pose,
+    /// Poses by animation and time, instances at the same point of an animation and the shadow
+    /// and scene passes of a frame share one
+    pose_cache: RefCell<HashMap<(usize, u32), Pose>>,
+    /// Per instance transforms and tints, shared by every mesh's VAO
+    pub(crate) instance_buffer: u32,
+    /// Vertices and indices on the GPU, textures are counted by Assets
+    pub(crate) mesh_bytes: usize,
 }
 
 impl Model {
-    pub fn new(gl: &gl::Gl, path: &str,image_file:&str) -> Model {
-        let cornell_box = tobj::load_obj(path.as_ref());
-        assert!(cornell_box.is_ok());
-        let (models, _materials) = cornell_box.unwrap();
+    /// Loads an OBJ, glTF or GLB file, the extension says which, with its textures and shader
+    /// from assets. The error says which file and why it could not be loaded.
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, path: &str, normals: Normals) -> Result<Model, String> {
+        let extension = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        match extension.as_str() {
+            "gltf" | "glb" => Model::from_gltf(gl, assets, path, normals),
+            _ => Model::from_obj(gl, assets, path, normals),
+        }
+    }
 
-        let texture = create_texture(&gl, image_file);
+    /// Each mesh is drawn with the map_Kd texture of its material
+    fn from_obj(gl: &gl::Gl, assets: &mut Assets, path: &str, normals: Normals) -> Result<Model, String> {
+        let (models, materials) = tobj::load_obj(path.as_ref()).map_err(|e| format!("{} {:?}", path, e))?;
 
-        let mut sub_models = Vec::<SubModel>::new();
+        let mut textures = MaterialTextures::new(path);
+
+        let mut meshes = Vec::new();
         for model in models.iter() {
-            let mut vertices: Vec<Vertex> = Vec::new();
-            let mut indices: Vec<u32> = Vec::new();
             let mesh = &model.mesh;
-            indices.append(&mut model.mesh.indices.clone());
             let num_vertices = mesh.positions.len() / 3;
-            let (p,  t) = (&mesh.positions, &mesh.texcoords);
-            for i in 0..num_vertices {
-                vertices.push(Vertex {
-                    position: vec3(p[i * 3], p[i * 3 + 1], p[i * 3 + 2]),
-                    tex_coords: vec2(t[i * 2], t[i * 2 + 1]),
-                })
+            let has_normals = mesh.normals.len() == mesh.positions.len();
+            let has_uvs = mesh.texcoords.len() == num_vertices * 2;
+            if !has_uvs && num_vertices > 0 {
+                println!("{} mesh {} has no texture coordinates", path, model.name);
             }
+
+            let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
+            let vertices: Vec<Vertex> = (0..num_vertices).map(|i| Vertex {
+                position: vec3(p[i * 3], p[i * 3 + 1], p[i * 3 + 2]),
+                normal: if has_normals { vec3(n[i * 3], n[i * 3 + 1], n[i * 3 + 2]) } else { Vector3::zero() },
+                tex_coords: if has_uvs { vec2(t[i * 2], t[i * 2 + 1]) } else { vec2(0.0, 0.0) },
+            }).collect();
+
+            let mtl = mesh.material_id.and_then(|id| materials.get(id).map(|m| (id, m)));
+            let (material, texture) = match mtl {
+                Some((id, mtl)) => (Material::from_mtl(mtl), textures.texture(gl, assets, id, mtl)),
+                None => {
+                    println!("{} mesh {} has no material, using the default texture", path, model.name);
+                    (Material::default(), assets.white(gl))
+                }
+            };
+            let data = MeshData {
+                name: model.name.clone(),
+                node: 0,
+                vertices,
+                indices: mesh.indices.clone(),
+                has_normals,
+                material,
+            };
+            meshes.push((data, texture));
+        }
+
+        Model::upload(gl, assets, path, meshes, normals, vec![Node::default()], Vec::new())
+    }
+
+    /// Meshes are drawn with the base colour texture and factor of their material, metallic and
+    /// roughness are turned into the nearest Blinn-Phong material
+    fn from_gltf(gl: &gl::Gl, assets: &mut Assets, path: &str, normals: Normals) -> Result<Model, String> {
+        let GltfData { meshes: gltf_meshes, images, nodes, animations } = load_gltf(path)?;
+
+        let mut meshes = Vec::new();
+        for mesh in gltf_meshes {
+            let texture = match mesh.image.and_then(|i| images.get(i).map(|image| (i, image))) {
+                // images are named by their index, they may be inside the file
+                Some((i, Some(image))) => assets.image_texture(gl, &format!("{}#{}", path, i), image),
+                _ => assets.white(gl),
+            };
+            meshes.push((mesh.data, texture));
+        }
+
+        Model::upload(gl, assets, path, meshes, normals, nodes, animations)
+    }
+
+    /// Fills in missing normals and sends the meshes to the GPU
+    fn upload(gl: &gl::Gl, assets: &mut Assets, path: &str, meshes: Vec<(MeshData, u32)>, normals: Normals,
+              nodes: Vec<Node>, animations: Vec<Animation>) -> Result<Model, String> {
+        let bind_pose = global_matrices(&nodes);
+        let instance_buffer = create_instance_buffer(gl);
+
+        let mut sub_models = Vec::<SubModel>::new();
+        let mut bounding_radius: f32 = 0.0;
+        let mut mesh_bytes = 0;
+        for (mesh, texture) in meshes {
+            let MeshData { name, node, mut vertices, mut indices, has_normals, material } = mesh;
+            if vertices.is_empty() || indices.len() < 3 {
+                println!("{} mesh {} has no faces, skipping it", path, name);
+                continue;
+            }
+            if let Some(bad) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+                return Err(format!("{} mesh {} index {} is past the last vertex", path, name, bad));
+            }
+            let node_matrix = *bind_pose.get(node).ok_or(format!("{} mesh {} uses missing node {}", path, name, node))?;
+            indices.truncate(indices.len() / 3 * 3);
+            if !has_normals {
+                match normals {
+                    Normals::Smooth => smooth_normals(&mut vertices, &indices),
+                    Normals::Flat => {
+                        let (flat_vertices, flat_indices) = flat_normals(&vertices, &indices);
+                        vertices = flat_vertices;
+                        indices = flat_indices;
+                    }
+                }
+            }
+            for vertex in vertices.iter() {
+                let position = (node_matrix * vertex.position.extend(1.0)).truncate();
+                bounding_radius = bounding_radius.max(position.magnitude());
+            }
+
             vertices.shrink_to_fit();
             indices.shrink_to_fit();
             let indices_len = indices.len();
-            let vao = setup_mesh(gl, vertices, indices);
+            mesh_bytes = mesh_bytes + vertices.len() * size_of::<Vertex>() + indices_len * size_of::<u32>();
+            let vao = setup_mesh(gl, vertices, indices, instance_buffer);
             let sub_model = SubModel {
                 texture,
+                material,
                 indices_len,
                 vao,
+                node,
             };
             sub_models.push(sub_model);
         }
+        if sub_models.is_empty() {
+            return Err(format!("{} has nothing to draw", path));
+        }
 
-        let our_shader = create_shader(&gl, IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE);
+        let our_shader = assets.shader(gl, "model", IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE);
 
-        Model {
+        Ok(Model {
             our_shader,
             sub_models,
+            bounding_radius,
+            nodes,
+            animations,
+            bind_pose: Rc::new(bind_pose),
+            pose_cache: RefCell::new(HashMap::new()),
+            instance_buffer,
+            mesh_bytes,
+        })
+    }
+
+    /// Index of the animation with this name
+    pub fn animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|a| a.name == name)
+    }
+
+    /// Matrix of every node at this many seconds into an animation, or the bind pose
+    pub fn pose(&self, animation: Option<usize>, time: f32) -> Pose {
+        let index = match animation.filter(|a| *a < self.animations.len()) {
+            Some(index) => index,
+            None => return Rc::clone(&self.bind_pose),
+        };
+        let key = (index, time.to_bits());
+        if let Some(pose) = self.pose_cache.borrow().get(&key) {
+            return Rc::clone(pose);
+        }
+        let mut nodes = self.nodes.clone();
+        self.animations[index].apply(time, &mut nodes);
+        let pose = Rc::new(global_matrices(&nodes));
+        let mut cache = self.pose_cache.borrow_mut();
+        if cache.len() >= POSE_CACHE_SIZE {
+            cache.clear();
         }
+        cache.insert(key, Rc::clone(&pose));
+        pose
     }
+}
 
+/// Textures for the materials of one OBJ file, looked up once however many meshes use them
+struct MaterialTextures {
+    directory: String,
+    by_material: HashMap<usize, u32>,
 }
 
-fn setup_mesh(gl: &gl::Gl, vertices: Vec<Vertex>, indices: Vec<u32>) -> u32 {
+impl MaterialTextures {
+    fn new(obj_path: &str) -> MaterialTextures {
+        let directory = Path::new(obj_path).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        MaterialTextures {
+            directory,
+            by_material: HashMap::new(),
+        }
+    }
+
+    /// map_Kd is relative to the OBJ file, without one the texture is plain white so the material colour shows through
+    fn texture(&mut self, gl: &gl::Gl, assets: &mut Assets, id: usize, material: &tobj::Material) -> u32 {
+        if let Some(texture) = self.by_material.get(&id) {
+            return *texture;
+        }
+        let texture = if material.diffuse_texture.is_empty() {
+            println!("material {} has no map_Kd, using the default texture", material.name);
+            assets.white(gl)
+        } else {
+            let file = Path::new(&self.directory).join(&material.diffuse_texture);
+            match assets.texture(gl, &file.to_string_lossy()) {
+                Ok(texture) => texture,
+                Err(e) => {
+                    println!("material {} unable to load texture {}, using the default texture", material.name, e);
+                    assets.white(gl)
+                }
+            }
+        };
+        self.by_material.insert(id, texture);
+        texture
+    }
+}
+
+fn face_normal(vertices: &[Vertex], face: &[u32]) -> Vector3<f32> {
+    let position = |i: u32| vertices[i as usize].position;
+    (position(face[1]) - position(face[0])).cross(position(face[2]) - position(face[0]))
+}
+
+/// Each vertex gets the average of the faces it is part of
+fn smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::zero(); vertices.len()];
+    for face in indices.chunks(3) {
+        // not normalised, so bigger faces count for more
+        let normal = face_normal(vertices, face);
+        for i in face {
+            normals[*i as usize] += normal;
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { vec3(0.0, 1.0, 0.0) };
+    }
+}
+
+/// Vertices are no longer shared so that each face can have its own normal
+fn flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat = Vec::with_capacity(indices.len());
+    for face in indices.chunks(3) {
+        let normal = face_normal(vertices, face);
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { vec3(0.0, 1.0, 0.0) };
+        for i in face {
+            flat.push(Vertex { normal, ..vertices[*i as usize] });
+        }
+    }
+    let indices = (0..flat.len() as u32).collect();
+    (flat, indices)
+}
+
+fn setup_mesh(gl: &gl::Gl, vertices: Vec<Vertex>, indices: Vec<u32>, instance_buffer: u32) -> u32 {
     let mut vao: u32 = 0;
     let mut vbo: u32 = 0;
     let mut ebo: u32 = 0;
@@ -144,8 +490,12 @@ fn setup_mesh(gl: &gl::Gl, vertices: Vec<Vertex>, indices: Vec<u32>) -> u32 {
         gl.EnableVertexAttribArray(1);
         gl.VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, tex_coords) as *const c_void);
 
+        gl.EnableVertexAttribArray(2);
+        gl.VertexAttribPointer(2, 3, gl::FLOAT, gl::FALSE, size, offset_of!(Vertex, normal) as *const c_void);
+
         gl.BindVertexArray(0);
     }
+    add_instance_attributes(gl, vao, instance_buffer);
     vao
 }
 
@@ -1,7 +1,11 @@
 use std::{ptr, str};
+use std::collections::HashMap;
 use std::ffi::CString;
 
+use cgmath::{Matrix, Matrix4, Vector3, Vector4};
+
 use crate::gl;
+use crate::gl_helper::camera_uniforms::{CAMERA_BINDING, CAMERA_BLOCK};
 
 pub fn create_shader(gl: &gl::Gl, image_vertex_shader_source:&str, image_fragment_shader_source:&str) -> u32 {
     unsafe {
@@ -43,4 +47,98 @@ pub fn create_shader(gl: &gl::Gl, image_vertex_shader_source:&str, image_fragmen
         gl.DeleteShader(fragment_shader);
         shader_program
     }
-}
\ No newline at end of file
+}
+/// A linked program with the locations of all its uniforms looked up once, when it is linked.
+/// The setters work on the program in use, like the GL calls they wrap.
+#[derive(Clone)]
+pub struct ShaderProgram {
+    pub id: u32,
+    uniforms: HashMap<String, i32>,
+}
+
+impl ShaderProgram {
+    /// Compiles and links the program, then reads its active uniforms. A Camera uniform block
+    /// is bound to the shared camera uniform buffer.
+    pub fn new(gl: &gl::Gl, vertex_shader_source: &str, fragment_shader_source: &str) -> ShaderProgram {
+        let id = create_shader(gl, vertex_shader_source, fragment_shader_source);
+        let mut uniforms = HashMap::new();
+        unsafe {
+            let mut count = 0;
+            gl.GetProgramiv(id, gl::ACTIVE_UNIFORMS, &mut count);
+            let mut name_buffer = vec![0u8; 256];
+            for index in 0..count as u32 {
+                let mut length = 0;
+                let mut size = 0;
+                let mut kind = 0;
+                gl.GetActiveUniform(id, index, name_buffer.len() as i32, &mut length, &mut size, &mut kind,
+                                    name_buffer.as_mut_ptr() as *mut gl::types::GLchar);
+                let name = String::from_utf8_lossy(&name_buffer[..length as usize]).to_string();
+                // arrays are reported as name[0], every element is looked up so it can be set by name[i]
+                let (base, is_array) = match name.strip_suffix("[0]") {
+                    Some(base) => (base.to_string(), true),
+                    None => (name, false),
+                };
+                let names = if is_array { (0..size).map(|i| format!("{}[{}]", base, i)).collect() } else { vec![base] };
+                for name in names {
+                    let location = gl.GetUniformLocation(id, CString::new(name.as_str()).unwrap().as_ptr());
+                    // members of a uniform block have no location
+                    if location >= 0 {
+                        uniforms.insert(name, location);
+                    }
+                }
+            }
+
+            let block = gl.GetUniformBlockIndex(id, CString::new(CAMERA_BLOCK).unwrap().as_ptr());
+            if block != gl::INVALID_INDEX {
+                gl.UniformBlockBinding(id, block, CAMERA_BINDING);
+            }
+        }
+        ShaderProgram { id, uniforms }
+    }
+
+    /// Uniforms found when linking, each element of an array counts as one
+    pub fn uniform_count(&self) -> usize {
+        self.uniforms.len()
+    }
+
+    pub fn use_program(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.UseProgram(self.id);
+        }
+    }
+
+    /// -1 for a uniform the program does not have, or that the compiler optimised away, which GL ignores
+    pub fn location(&self, name: &str) -> i32 {
+        *self.uniforms.get(name).unwrap_or(&-1)
+    }
+
+    pub fn set_matrix4(&self, gl: &gl::Gl, name: &str, value: &Matrix4<f32>) {
+        unsafe {
+            gl.UniformMatrix4fv(self.location(name), 1, gl::FALSE, value.as_ptr());
+        }
+    }
+
+    pub fn set_vector4(&self, gl: &gl::Gl, name: &str, value: Vector4<f32>) {
+        unsafe {
+            gl.Uniform4f(self.location(name), value.x, value.y, value.z, value.w);
+        }
+    }
+
+    pub fn set_vector3(&self, gl: &gl::Gl, name: &str, value: Vector3<f32>) {
+        unsafe {
+            gl.Uniform3f(self.location(name), value.x, value.y, value.z);
+        }
+    }
+
+    pub fn set_float(&self, gl: &gl::Gl, name: &str, value: f32) {
+        unsafe {
+            gl.Uniform1f(self.location(name), value);
+        }
+    }
+
+    pub fn set_int(&self, gl: &gl::Gl, name: &str, value: i32) {
+        unsafe {
+            gl.Uniform1i(self.location(name), value);
+        }
+    }
+}
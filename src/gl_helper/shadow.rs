@@ -0,0 +1,161 @@
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+use cgmath::{ortho, vec3, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+
+use crate::gl;
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::assets::Assets;
+use crate::gl_helper::instancing::{draw_arrays_instanced, draw_elements_instanced};
+use crate::settings::Settings;
+
+const DEPTH_VERTEX_SHADER_SOURCE: &str = "#version 300 es
+layout (location = 0) in vec3 aPos;
+layout (location = 3) in mat4 aTransform;
+
+uniform mat4 lightSpace;
+
+void main()
+{
+	gl_Position = lightSpace * aTransform * vec4(aPos, 1.0);
+}
+";
+
+const DEPTH_FRAGMENT_SHADER_SOURCE: &str = "#version 300 es
+precision mediump float;
+
+void main()
+{
+}
+";
+
+/// How far back along the sun direction the light looks from, the depth range is twice this
+const LIGHT_DISTANCE: f32 = 3.0;
+
+pub struct ShadowSettings {
+    /// Off saves a render pass and a big texture on slow machines
+    pub enabled: bool,
+    /// Width and height of the shadow map in texels
+    pub size: u32,
+}
+
+impl ShadowSettings {
+    pub fn from_settings(settings: &Settings) -> ShadowSettings {
+        #[cfg(not(target_os = "emscripten"))]
+            let default_size = 2048;
+        #[cfg(target_os = "emscripten")]
+            let default_size = 1024;
+        ShadowSettings {
+            enabled: settings.get_or("shadows.enabled", true),
+            size: settings.get_or("shadows.size", default_size),
+        }
+    }
+}
+
+/// Depth of everything that casts a shadow as seen from the sun. Casters are drawn into it with
+/// cast_elements or cast_arrays between begin and end, then receivers compare against it.
+pub struct ShadowMap {
+    framebuffer: u32,
+    pub(crate) depth_texture: u32,
+    size: i32,
+    depth_shader: Rc<ShaderProgram>,
+    /// Takes world positions to the sun's clip space
+    pub(crate) light_space: Matrix4<f32>,
+}
+
+impl ShadowMap {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, size: u32) -> Result<ShadowMap, String> {
+        let size = size.max(64) as i32;
+        unsafe {
+            let mut depth_texture: u32 = 0;
+            gl.GenTextures(1, &mut depth_texture);
+            gl.BindTexture(gl::TEXTURE_2D, depth_texture);
+            gl.TexImage2D(gl::TEXTURE_2D,
+                          0,
+                          gl::DEPTH_COMPONENT24 as i32,
+                          size,
+                          size,
+                          0,
+                          gl::DEPTH_COMPONENT,
+                          gl::UNSIGNED_INT,
+                          ptr::null::<c_void>());
+            // linear with a compare mode gives each lookup a softened edge
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as i32);
+
+            let mut framebuffer: u32 = 0;
+            gl.GenFramebuffers(1, &mut framebuffer);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl.FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth_texture, 0);
+            let none = gl::NONE;
+            gl.DrawBuffers(1, &none);
+            gl.ReadBuffer(gl::NONE);
+            let status = gl.CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                gl.DeleteFramebuffers(1, &framebuffer);
+                gl.DeleteTextures(1, &depth_texture);
+                return Err(format!("shadow map framebuffer incomplete {:#x}", status));
+            }
+            // 24 bit depth is stored in 32 bits
+            assets.add_texture("shadow map", depth_texture, size as usize * size as usize * 4);
+
+            Ok(ShadowMap {
+                framebuffer,
+                depth_texture,
+                size,
+                depth_shader: assets.shader(gl, "shadow depth", DEPTH_VERTEX_SHADER_SOURCE, DEPTH_FRAGMENT_SHADER_SOURCE),
+                light_space: Matrix4::from_scale(1.0),
+            })
+        }
+    }
+
+    /// Points the sun at a box reaching half_size from centre in every direction,
+    /// anything outside it casts no shadow
+    pub fn aim(&mut self, sun_direction: Vector3<f32>, centre: Vector3<f32>, half_size: f32) {
+        let direction = sun_direction.normalize();
+        let centre = Point3::from_vec(centre);
+        // any up will do as long as it is not the way the sun shines
+        let up = if direction.z.abs() > 0.9 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 0.0, 1.0) };
+        let view = Matrix4::look_at(centre - direction * LIGHT_DISTANCE, centre, up);
+        let projection = ortho(-half_size, half_size, -half_size, half_size, 0.01, LIGHT_DISTANCE * 2.0);
+        self.light_space = projection * view;
+    }
+
+    /// Starts drawing casters into the shadow map
+    pub fn begin(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl.Viewport(0, 0, self.size, self.size);
+            gl.Enable(gl::DEPTH_TEST);
+            gl.Clear(gl::DEPTH_BUFFER_BIT);
+        }
+        self.depth_shader.use_program(gl);
+        self.depth_shader.set_matrix4(gl, "lightSpace", &self.light_space);
+    }
+
+    /// Draws the instances of an indexed mesh that have been uploaded to its instance buffer,
+    /// the VAO needs positions in attribute 0
+    pub fn cast_elements(&self, gl: &gl::Gl, vao: u32, count: usize, instances: usize) {
+        draw_elements_instanced(gl, vao, count, instances);
+    }
+
+    /// Same as cast_elements for a mesh that is not indexed
+    pub fn cast_arrays(&self, gl: &gl::Gl, vao: u32, count: usize, instances: usize) {
+        draw_arrays_instanced(gl, vao, count, instances);
+    }
+
+    /// Back to drawing on the screen
+    pub fn end(&self, gl: &gl::Gl, viewport: (u32, u32)) {
+        unsafe {
+            gl.BindVertexArray(0);
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl.Viewport(0, 0, viewport.0 as i32, viewport.1 as i32);
+        }
+    }
+}
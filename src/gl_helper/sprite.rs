@@ -1,13 +1,14 @@
 use std::ptr;
+use std::rc::Rc;
 use std::str;
 
-use cgmath::{vec3, perspective, Deg, Matrix4 };
+use cgmath::{vec3, vec4, Matrix4, Vector4};
 
-use crate::{gl, HEIGHT, WIDTH, SCALE_TO_SCREEN};
-use crate::gl_helper::shader::create_shader;
-use crate::gl_helper::texture::{create_texture_png, create_texture_jpg};
+use crate::{gl, SCALE_TO_SCREEN};
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::assets::Assets;
 use crate::gl_helper::vertex::create_vertex;
-use crate::gl_helper::gl_matrix4;
+use crate::gl_helper::instancing::count_draw_call;
 
 const IMAGE_VERTEX_SHADER_SOURCE: &str ="#version 300 es
 layout (location = 0) in vec3 aPos;
@@ -20,13 +21,14 @@ out vec2 TexCoord;
 uniform mat4 transform;
 uniform mat4 projection;
 uniform mat4 view;
+uniform vec4 uvRect;
 
 void main()
 {
 
 	gl_Position = projection * view * transform * vec4(aPos ,1.0) ;
 	ourColor = aColor;
-	TexCoord = vec2(aTexCoord.x, aTexCoord.y);
+	TexCoord = uvRect.xy + vec2(aTexCoord.x, aTexCoord.y) * uvRect.zw;
 }
 ";
 
@@ -38,29 +40,36 @@ in vec3 ourColor;
 in vec2 TexCoord;
 
 uniform sampler2D texture1;
+uniform vec4 tint;
 
 void main()
 {
-	FragColor = texture(texture1, TexCoord);
+	FragColor = texture(texture1, TexCoord) * tint;
     if(FragColor.a < 0.1)
         discard;
 }
 ";
 
 pub struct Sprite {
-    shader_program: u32,
+    shader_program: Rc<ShaderProgram>,
     vao: u32,
     pub texture: u32,
     pub transform: Matrix4<f32>,
-    pub rotate: Matrix4<f32>,
-    pub forward: Matrix4<f32>,
+    /// part of the texture to draw as x, y, width, height in texture coordinates
+    pub uv_rect: Vector4<f32>,
+    pub tint: Vector4<f32>,
 }
 
 
 
 impl Sprite {
-    pub fn new(gl: &gl::Gl, x: f32, y: f32, image_file: &str, width: f32, height: f32, texture_ref: Option<u32>) -> Sprite {
-        let shader_program = create_shader(&gl, IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE);
+    /// Draws texture_ref if given, otherwise the texture loaded from image_file
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, x: f32, y: f32, image_file: &str, width: f32, height: f32, texture_ref: Option<u32>) -> Result<Sprite, String> {
+        let texture = match texture_ref {
+            Some(texture) => texture,
+            None => assets.texture(gl, image_file)?,
+        };
+        let shader_program = assets.shader(gl, "sprite", IMAGE_VERTEX_SHADER_SOURCE, IMAGE_FRAGMENT_SHADER_SOURCE);
 
         // set up vertex data (and buffer(s)) and configure vertex attributes
         // ------------------------------------------------------------------
@@ -81,41 +90,29 @@ impl Sprite {
 
         let vao = create_vertex(&gl, &vertices, &indices);
 
-
-        let texture = if texture_ref.is_none() {
-            if image_file.ends_with(".png") {
-                create_texture_png(&gl, image_file)
-            } else {
-                create_texture_jpg(&gl, image_file)
-            }
-        } else {
-            texture_ref.unwrap()
-        };
-
-        Sprite {
-            shader_program: shader_program,
-            vao: vao,
-            texture: texture,
+        Ok(Sprite {
+            shader_program,
+            vao,
+            texture,
             transform: Matrix4::<f32>::from_translation(vec3(x * SCALE_TO_SCREEN, y * SCALE_TO_SCREEN, 0.0)),
-            rotate: Matrix4::<f32>::from_translation(vec3(0.0, 0.0, 0.0)),
-            forward: Matrix4::<f32>::from_translation(vec3(0.0, 0.0, 0.0)),
-        }
+            uv_rect: vec4(0.0, 0.0, 1.0, 1.0),
+            tint: vec4(1.0, 1.0, 1.0, 1.0),
+        })
     }
-    pub fn render(&mut self, gl: &gl::Gl) {
-        let view: Matrix4<f32> = Matrix4::from_translation(vec3(0., 0., -30.0));
-        let projection: Matrix4<f32> = perspective(Deg(45.0), WIDTH as f32 / HEIGHT as f32, 0.1, 100.0);
-
+    /// Draws with the caller's view and projection, e.g. an orthographic projection for the HUD
+    pub fn render_with(&mut self, gl: &gl::Gl, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
         unsafe {
             gl.BindTexture(gl::TEXTURE_2D, self.texture);
-            gl.UseProgram(self.shader_program);
+            self.shader_program.use_program(gl);
             gl.BindVertexArray(self.vao);
 
+            self.shader_program.set_matrix4(gl, "transform", &self.transform);
+            self.shader_program.set_matrix4(gl, "view", view);
+            self.shader_program.set_matrix4(gl, "projection", projection);
+            self.shader_program.set_vector4(gl, "uvRect", self.uv_rect);
+            self.shader_program.set_vector4(gl, "tint", self.tint);
 
-            //self.transform = self.transform * self.rotate * self.forward;
-            gl_matrix4(gl,self.shader_program, self.transform, "transform");
-            gl_matrix4(gl,self.shader_program, view, "view");
-            gl_matrix4(gl,self.shader_program, projection, "projection");
-
+            count_draw_call();
             gl.DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null());
         }
     }
@@ -5,27 +5,18 @@ use std::str;
 use crate::gl;
 use image::GenericImage;
 
-pub fn create_texture(gl: &gl::Gl, filename: &str) -> u32 {
-    if filename.ends_with("png") {
-        create_texture_png(gl,filename)
-    } else {
-        create_texture_jpg(gl,filename)
-    }
-}
-pub fn create_texture_png(gl: &gl::Gl, filename: &str) -> u32 {
+/// Texture from an image already in memory, such as one embedded in a glTF file. Sampled nearest
+/// without mipmaps, which keeps the pixels of the bitmap font sharp.
+pub fn texture_from_image(gl: &gl::Gl, img: &image::DynamicImage) -> u32 {
+    let data = img.flipv().to_rgba().into_raw();
     unsafe {
         let mut texture: u32 = 0;
         gl.GenTextures(1, &mut texture);
-        gl.BindTexture(gl::TEXTURE_2D, texture); // all upcoming GL_TEXTURE_2D operations now have effect on this texture object
-        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32); // set texture wrapping to gl::REPEAT (default wrapping method)
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
         gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
         gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
         gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-
-        // load image, create texture and generate mipmaps
-
-        let img = image::open(&Path::new(filename)).expect("Failed to load texture");
-        let data = img.flipv().raw_pixels();
         gl.TexImage2D(gl::TEXTURE_2D,
                       0,
                       gl::RGBA as i32,
@@ -35,34 +26,64 @@ pub fn create_texture_png(gl: &gl::Gl, filename: &str) -> u32 {
                       gl::RGBA,
                       gl::UNSIGNED_BYTE,
                       &data[0] as *const u8 as *const c_void);
-        gl.GenerateMipmap(gl::TEXTURE_2D);
         texture
     }
 }
-pub fn create_texture_jpg(gl: &gl::Gl, filename: &str) -> u32 {
+
+/// Texture from RGBA pixels made in code, rows are bottom up as GL expects
+pub fn create_texture_rgba(gl: &gl::Gl, width: u32, height: u32, pixels: &[u8]) -> u32 {
     unsafe {
         let mut texture: u32 = 0;
         gl.GenTextures(1, &mut texture);
-        gl.BindTexture(gl::TEXTURE_2D, texture); // all upcoming GL_TEXTURE_2D operations now have effect on this texture object
-        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32); // set texture wrapping to gl::REPEAT (default wrapping method)
-        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-
-        // load image, create texture and generate mipmaps
-
-        let img = image::open(&Path::new(filename)).expect("Failed to load texture");
-        let data = img.flipv().raw_pixels();
+        gl.BindTexture(gl::TEXTURE_2D, texture);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
         gl.TexImage2D(gl::TEXTURE_2D,
                       0,
-                      gl::RGB as i32,
-                      img.width() as i32,
-                      img.height() as i32,
+                      gl::RGBA as i32,
+                      width as i32,
+                      height as i32,
                       0,
-                      gl::RGB,
+                      gl::RGBA,
                       gl::UNSIGNED_BYTE,
-                      &data[0] as *const u8 as *const c_void);
-        gl.GenerateMipmap(gl::TEXTURE_2D);
+                      &pixels[0] as *const u8 as *const c_void);
         texture
     }
 }
+
+/// Cube map from six images in a directory named px, nx, py, ny, pz and nz.png, +x first, and
+/// its size in bytes. Cube map faces start at the top so they are not flipped.
+pub fn load_cubemap(gl: &gl::Gl, directory: &str) -> Result<(u32, usize), String> {
+    let mut faces = Vec::new();
+    for name in &["px", "nx", "py", "ny", "pz", "nz"] {
+        let filename = Path::new(directory).join(format!("{}.png", name));
+        let img = image::open(&filename).map_err(|e| format!("{} {}", filename.display(), e))?;
+        faces.push(img);
+    }
+    let bytes = faces.iter().map(|img| img.width() as usize * img.height() as usize * 4).sum();
+    unsafe {
+        let mut texture: u32 = 0;
+        gl.GenTextures(1, &mut texture);
+        gl.BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+        for (i, img) in faces.iter().enumerate() {
+            let data = img.to_rgba().into_raw();
+            gl.TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
+                          0,
+                          gl::RGBA as i32,
+                          img.width() as i32,
+                          img.height() as i32,
+                          0,
+                          gl::RGBA,
+                          gl::UNSIGNED_BYTE,
+                          &data[0] as *const u8 as *const c_void);
+        }
+        gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl.TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+        Ok((texture, bytes))
+    }
+}
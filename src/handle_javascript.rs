@@ -24,11 +24,6 @@ pub extern "C" fn javascript_read_stats() -> *const c_char {
         return on_heap.as_ptr();
     }
 }
-#[cfg(target_os = "emscripten")]
-extern "C" {
-    pub fn start_javascript_play_sound(sound_id: i32) -> i32;
-}
-
 #[cfg(target_os = "emscripten")]
 extern "C" {
     pub fn start_game() -> i32;
@@ -0,0 +1,258 @@
+use cgmath::{vec3, vec4, ortho, Matrix4, Vector4, SquareMatrix};
+
+use crate::gl;
+use crate::gl_helper::glyph_atlas::GlyphAtlas;
+use crate::gl_helper::sprite::Sprite;
+use crate::gl_helper::assets::Assets;
+
+const FONT_IMAGE: &str = "resources/font.png";
+pub const TTF_FONT: &str = "resources/fonts/DejaVuSansMono-Bold.ttf";
+const FONT_COLUMNS: u32 = 16;
+const FONT_ROWS: u32 = 6;
+const FIRST_CHAR: u32 = 32;
+/// Size of a character in the font image, the HUD scales it up on bigger displays
+const CHAR_WIDTH: f32 = 8.0;
+const CHAR_HEIGHT: f32 = 16.0;
+const MESSAGE_FRAMES: i32 = 180;
+
+pub const WHITE: Vector4<f32> = Vector4 { x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
+pub const YELLOW: Vector4<f32> = Vector4 { x: 1.0, y: 0.9, z: 0.2, w: 1.0 };
+pub const GREEN: Vector4<f32> = Vector4 { x: 0.3, y: 1.0, z: 0.3, w: 1.0 };
+
+/// Something that can draw a line of text in pixel coordinates. A scale of 1 is about 16 pixels high.
+pub trait TextFont {
+    /// Scale that suits the size of the display
+    fn scale_for(&self, viewport: (u32, u32)) -> f32;
+    fn text_width(&self, text: &str, scale: f32) -> f32;
+    fn line_height(&self, scale: f32) -> f32;
+    /// Draws text with its top left corner at x,y in pixels
+    fn draw_text(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, text: &str, x: f32, y: f32, scale: f32, colour: Vector4<f32>);
+}
+
+/// Fixed width font drawn a character at a time from one texture, the 96 printable
+/// ASCII characters are laid out 16 to a row
+pub struct BitmapFont {
+    sprite: Sprite,
+}
+
+impl BitmapFont {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Result<BitmapFont, String> {
+        Ok(BitmapFont {
+            sprite: Sprite::new(gl, assets, 0.0, 0.0, FONT_IMAGE, 1.0, 1.0, None)?,
+        })
+    }
+}
+
+impl TextFont for BitmapFont {
+    /// Characters are scaled up in whole steps so they stay sharp
+    fn scale_for(&self, viewport: (u32, u32)) -> f32 {
+        (viewport.1 as f32 / 400.0).floor().max(1.0)
+    }
+
+    fn text_width(&self, text: &str, scale: f32) -> f32 {
+        text.chars().count() as f32 * CHAR_WIDTH * scale
+    }
+
+    fn line_height(&self, scale: f32) -> f32 {
+        CHAR_HEIGHT * scale
+    }
+
+    /// Characters outside of ASCII show as ?
+    fn draw_text(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, text: &str, x: f32, y: f32, scale: f32, colour: Vector4<f32>) {
+        let view = Matrix4::identity();
+        let (width, height) = (CHAR_WIDTH * scale, CHAR_HEIGHT * scale);
+        self.sprite.tint = colour;
+        for (i, c) in text.chars().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let code = if (c as u32) >= FIRST_CHAR && (c as u32) < FIRST_CHAR + FONT_COLUMNS * FONT_ROWS { c as u32 } else { '?' as u32 };
+            let index = code - FIRST_CHAR;
+            let (column, row) = (index % FONT_COLUMNS, index / FONT_COLUMNS);
+            // the texture is flipped when loaded so the first row of characters is at the top
+            self.sprite.uv_rect = vec4(column as f32 / FONT_COLUMNS as f32,
+                                       1.0 - (row + 1) as f32 / FONT_ROWS as f32,
+                                       1.0 / FONT_COLUMNS as f32,
+                                       1.0 / FONT_ROWS as f32);
+            let left = x + i as f32 * width;
+            self.sprite.transform = Matrix4::from_translation(vec3(left + width / 2.0, y + height / 2.0, 0.0)) *
+                Matrix4::from_nonuniform_scale(width, -height, 1.0);
+            self.sprite.render_with(gl, &view, projection);
+        }
+    }
+}
+
+/// TrueType text with kerning, any size and any character the font has
+pub struct TtfFont {
+    atlas: GlyphAtlas,
+    sprite: Sprite,
+}
+
+impl TtfFont {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, font_file: &str) -> Result<TtfFont, String> {
+        let atlas = GlyphAtlas::new(gl, assets, font_file)?;
+        let sprite = Sprite::new(gl, assets, 0.0, 0.0, font_file, 1.0, 1.0, Some(atlas.texture))?;
+        Ok(TtfFont { atlas, sprite })
+    }
+
+    fn px_size(scale: f32) -> u32 {
+        (CHAR_HEIGHT * scale).round().max(1.0) as u32
+    }
+}
+
+impl TextFont for TtfFont {
+    fn scale_for(&self, viewport: (u32, u32)) -> f32 {
+        (viewport.1 as f32 / 400.0).max(1.0)
+    }
+
+    fn text_width(&self, text: &str, scale: f32) -> f32 {
+        self.atlas.text_width(text, TtfFont::px_size(scale))
+    }
+
+    fn line_height(&self, scale: f32) -> f32 {
+        self.atlas.line_height(TtfFont::px_size(scale))
+    }
+
+    fn draw_text(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, text: &str, x: f32, y: f32, scale: f32, colour: Vector4<f32>) {
+        let px_size = TtfFont::px_size(scale);
+        let baseline = (y + self.atlas.ascent(px_size)).round();
+        let placed = self.atlas.place(text, px_size);
+        self.atlas.upload(gl);
+
+        let view = Matrix4::identity();
+        self.sprite.tint = colour;
+        for (info, pen) in placed {
+            // whole pixels keep the glyphs lined up with the texels
+            let left = (x + pen + info.left).round();
+            let top = baseline + info.top;
+            self.sprite.uv_rect = info.uv_rect;
+            self.sprite.transform = Matrix4::from_translation(vec3(left + info.width / 2.0, top + info.height / 2.0, 0.0)) *
+                Matrix4::from_nonuniform_scale(info.width, -info.height, 1.0);
+            self.sprite.render_with(gl, &view, projection);
+        }
+    }
+}
+
+/// What the HUD shows this frame
+pub struct HudInfo<'a> {
+    pub score: i32,
+    pub level: i32,
+    pub lives: Option<i32>,
+    pub weapon: &'a str,
+    pub paused: bool,
+    pub menu: Option<Vec<String>>,
+    /// Draw calls made for the scene, shown with the debug camera
+    pub draw_calls: Option<usize>,
+}
+
+struct Message {
+    text: String,
+    frames_left: i32,
+}
+
+/// Text drawn over the scene with an orthographic projection in pixels, the origin is the top left
+pub struct Hud {
+    pub font: Box<dyn TextFont>,
+    messages: Vec<Message>,
+}
+
+impl Hud {
+    /// Uses the TrueType font if one is given and it loads, otherwise the bitmap font
+    pub fn new(gl: &gl::Gl, assets: &mut Assets, ttf_file: Option<&str>) -> Result<Hud, String> {
+        let font: Box<dyn TextFont> = match ttf_file.map(|f| TtfFont::new(gl, assets, f)) {
+            Some(Ok(ttf)) => Box::new(ttf),
+            Some(Err(e)) => {
+                println!("unable to load font {}, using the bitmap font", e);
+                Box::new(BitmapFont::new(gl, assets)?)
+            }
+            None => Box::new(BitmapFont::new(gl, assets)?),
+        };
+        Ok(Hud {
+            font,
+            messages: Vec::new(),
+        })
+    }
+
+    /// Shows a message in the middle of the screen for a few seconds
+    pub fn message(&mut self, text: &str) {
+        self.messages.push(Message { text: text.to_string(), frames_left: MESSAGE_FRAMES });
+    }
+
+    pub fn projection(viewport: (u32, u32)) -> Matrix4<f32> {
+        ortho(0.0, viewport.0 as f32, viewport.1 as f32, 0.0, -1.0, 1.0)
+    }
+
+    /// Sets up blending without depth testing, call end_pass once drawing is done
+    pub fn begin_pass(gl: &gl::Gl) {
+        unsafe {
+            gl.Disable(gl::DEPTH_TEST);
+            gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+    }
+
+    pub fn end_pass(gl: &gl::Gl) {
+        unsafe {
+            gl.BlendFunc(gl::ONE, gl::ZERO);
+            gl.Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    pub fn render(&mut self, gl: &gl::Gl, viewport: (u32, u32), info: &HudInfo) {
+        let projection = Hud::projection(viewport);
+        let scale = self.font.scale_for(viewport);
+        let margin = 8.0 * scale;
+        let line = self.font.line_height(scale);
+        let (width, height) = (viewport.0 as f32, viewport.1 as f32);
+
+        Hud::begin_pass(gl);
+
+        self.font.draw_text(gl, &projection, &format!("SCORE {}", info.score), margin, margin, scale, WHITE);
+        let level = format!("LEVEL {}", info.level);
+        let level_x = width - margin - self.font.text_width(&level, scale);
+        self.font.draw_text(gl, &projection, &level, level_x, margin, scale, WHITE);
+
+        let bottom = height - margin - line;
+        if let Some(lives) = info.lives {
+            self.font.draw_text(gl, &projection, &format!("LIVES {}", lives), margin, bottom, scale, WHITE);
+        }
+        let weapon = format!("WEAPON {}", info.weapon.to_uppercase());
+        let weapon_x = width - margin - self.font.text_width(&weapon, scale);
+        self.font.draw_text(gl, &projection, &weapon, weapon_x, bottom, scale, WHITE);
+
+        if let Some(draw_calls) = info.draw_calls {
+            self.font.draw_text(gl, &projection, &format!("DRAWS {}", draw_calls), margin, margin + line, scale, GREEN);
+        }
+
+        let mut y = height / 3.0;
+        if info.paused {
+            self.draw_centred(gl, &projection, width, "PAUSED", y, scale * 2.0, YELLOW);
+            y = y + line * 2.0;
+        }
+        let messages: Vec<String> = self.messages.iter().map(|m| m.text.clone()).collect();
+        for text in messages {
+            self.draw_centred(gl, &projection, width, &text, y, scale, YELLOW);
+            y = y + line;
+        }
+        if let Some(menu) = &info.menu {
+            let menu_width = menu.iter().map(|l| self.font.text_width(l, scale)).fold(0.0, f32::max);
+            let x = ((width - menu_width) / 2.0).max(margin);
+            let mut y = margin + line * 2.0;
+            for text in menu {
+                self.font.draw_text(gl, &projection, text, x, y, scale, GREEN);
+                y = y + line;
+            }
+        }
+
+        Hud::end_pass(gl);
+
+        for message in &mut self.messages {
+            message.frames_left = message.frames_left - 1;
+        }
+        self.messages.retain(|m| m.frames_left > 0);
+    }
+
+    fn draw_centred(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, width: f32, text: &str, y: f32, scale: f32, colour: Vector4<f32>) {
+        let x = (width - self.font.text_width(text, scale)) / 2.0;
+        self.font.draw_text(gl, projection, text, x, y, scale, colour);
+    }
+}
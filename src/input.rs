@@ -0,0 +1,474 @@
+use std::collections::{HashMap, HashSet};
+
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+
+use crate::settings::Settings;
+
+/// Everything the player can do, independent of which keys are bound to it
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Action {
+    TurnLeft,
+    TurnRight,
+    PitchUp,
+    PitchDown,
+    Forward,
+    Backward,
+    StrafeLeft,
+    StrafeRight,
+    Ascend,
+    Descend,
+    Fire,
+    CameraMode,
+    DebugCamera,
+    ToggleFullscreen,
+    Pause,
+    Options,
+    Quit,
+}
+
+pub const ALL_ACTIONS: [Action; 17] = [
+    Action::TurnLeft,
+    Action::TurnRight,
+    Action::PitchUp,
+    Action::PitchDown,
+    Action::Forward,
+    Action::Backward,
+    Action::StrafeLeft,
+    Action::StrafeRight,
+    Action::Ascend,
+    Action::Descend,
+    Action::Fire,
+    Action::CameraMode,
+    Action::DebugCamera,
+    Action::ToggleFullscreen,
+    Action::Pause,
+    Action::Options,
+    Action::Quit,
+];
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::TurnLeft => "TurnLeft",
+            Action::TurnRight => "TurnRight",
+            Action::PitchUp => "PitchUp",
+            Action::PitchDown => "PitchDown",
+            Action::Forward => "Forward",
+            Action::Backward => "Backward",
+            Action::StrafeLeft => "StrafeLeft",
+            Action::StrafeRight => "StrafeRight",
+            Action::Ascend => "Ascend",
+            Action::Descend => "Descend",
+            Action::Fire => "Fire",
+            Action::CameraMode => "CameraMode",
+            Action::DebugCamera => "DebugCamera",
+            Action::ToggleFullscreen => "ToggleFullscreen",
+            Action::Pause => "Pause",
+            Action::Options => "Options",
+            Action::Quit => "Quit",
+        }
+    }
+
+    fn default_keys(&self) -> Vec<Keycode> {
+        match self {
+            Action::TurnLeft => vec![Keycode::Left],
+            Action::TurnRight => vec![Keycode::Right],
+            Action::PitchUp => vec![Keycode::Up],
+            Action::PitchDown => vec![Keycode::Down],
+            Action::Forward => vec![Keycode::LShift, Keycode::W],
+            Action::Backward => vec![Keycode::LCtrl, Keycode::S],
+            Action::StrafeLeft => vec![Keycode::A],
+            Action::StrafeRight => vec![Keycode::D],
+            Action::Ascend => vec![Keycode::E],
+            Action::Descend => vec![Keycode::Q],
+            Action::Fire => vec![Keycode::Space],
+            Action::CameraMode => vec![Keycode::C],
+            Action::DebugCamera => vec![Keycode::F3],
+            Action::ToggleFullscreen => vec![Keycode::F11],
+            Action::Pause => vec![Keycode::P],
+            Action::Options => vec![Keycode::F1],
+            Action::Quit => vec![Keycode::Escape],
+        }
+    }
+
+    /// Without a key there would be no way to pause, get back to the options or leave the game
+    pub fn needs_key(&self) -> bool {
+        matches!(self, Action::Pause | Action::Options | Action::Quit)
+    }
+
+    fn settings_key(&self) -> String {
+        format!("bind.{}", self.name())
+    }
+}
+
+pub struct Bindings {
+    keys: HashMap<Action, Vec<Keycode>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut keys = HashMap::new();
+        for action in ALL_ACTIONS.iter() {
+            keys.insert(*action, action.default_keys());
+        }
+        Bindings { keys }
+    }
+}
+
+impl Bindings {
+    /// Reads "bind.Action = Key, Key" entries, any action not in the settings keeps its default keys.
+    /// A key given to more than one action stays with the first, a key in the settings is taken off
+    /// any action still using its defaults, and an action that needs a key but is left with none
+    /// gets its defaults back.
+    pub fn from_settings(settings: &Settings) -> Bindings {
+        let mut bindings = Bindings::default();
+        let mut loaded: HashSet<Keycode> = HashSet::new();
+        for action in ALL_ACTIONS.iter() {
+            if let Some(value) = settings.get(&action.settings_key()) {
+                let mut keys = Vec::new();
+                for name in value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                    match Keycode::from_name(name) {
+                        Some(key) if loaded.contains(&key) => println!("key '{}' is already bound, not adding it to {}", name, action.name()),
+                        Some(key) => {
+                            loaded.insert(key);
+                            keys.push(key);
+                        }
+                        None => println!("unknown key '{}' for {}", name, action.name()),
+                    }
+                }
+                bindings.keys.insert(*action, keys);
+            }
+        }
+        // a settings.cfg from before strafing has A, D, W and S on turn and pitch, keys in the
+        // file come off the actions left on their defaults so they do not drive both
+        for (action, keys) in bindings.keys.iter_mut() {
+            if settings.get(&action.settings_key()).is_none() {
+                keys.retain(|k| !loaded.contains(k));
+            }
+        }
+        let mut left_without_key = Vec::new();
+        for action in ALL_ACTIONS.iter() {
+            if action.needs_key() && bindings.keys_for(*action).is_empty() {
+                println!("{} has no key, using {}", action.name(), Bindings::default().describe(*action));
+                for key in action.default_keys() {
+                    for other in bindings.actions_for(key) {
+                        println!("{} is taken off {} for {}", key.name(), other.name(), action.name());
+                        left_without_key.push(other);
+                    }
+                    bindings.bind(*action, key);
+                }
+            }
+        }
+        // an action that gave up its only key goes back to whichever of its own defaults are free
+        for action in left_without_key {
+            if bindings.keys_for(action).is_empty() {
+                let free: Vec<Keycode> = action.default_keys().into_iter()
+                    .filter(|key| bindings.actions_for(*key).is_empty())
+                    .collect();
+                if free.is_empty() {
+                    println!("{} has no key left", action.name());
+                } else {
+                    println!("{} has no key left, using {}", action.name(), free.iter().map(|k| k.name()).collect::<Vec<String>>().join(", "));
+                }
+                for key in free {
+                    bindings.bind(action, key);
+                }
+            }
+        }
+        bindings
+    }
+
+    pub fn write_to(&self, settings: &mut Settings) {
+        for action in ALL_ACTIONS.iter() {
+            settings.set(&action.settings_key(), self.describe(*action));
+        }
+    }
+
+    pub fn keys_for(&self, action: Action) -> &[Keycode] {
+        match self.keys.get(&action) {
+            Some(keys) => keys,
+            None => &[],
+        }
+    }
+
+    pub fn actions_for(&self, key: Keycode) -> Vec<Action> {
+        ALL_ACTIONS.iter().filter(|a| self.keys_for(**a).contains(&key)).cloned().collect()
+    }
+
+    /// Adds a key to an action, a key can only drive one action so it is taken off any other.
+    /// Returns false and changes nothing if that would leave an action that needs a key without one.
+    pub fn bind(&mut self, action: Action, key: Keycode) -> bool {
+        let last_key_of = ALL_ACTIONS.iter()
+            .find(|a| **a != action && a.needs_key() && self.keys_for(**a) == [key]);
+        if let Some(other) = last_key_of {
+            println!("{} is the only key for {}", key.name(), other.name());
+            return false;
+        }
+        for keys in self.keys.values_mut() {
+            keys.retain(|k| *k != key);
+        }
+        self.keys.entry(action).or_default().push(key);
+        true
+    }
+
+    /// Takes every key off an action, unless it needs a key
+    pub fn clear(&mut self, action: Action) -> bool {
+        if action.needs_key() {
+            println!("{} can not be left without a key", action.name());
+            return false;
+        }
+        self.keys.insert(action, Vec::new());
+        true
+    }
+
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<String> = self.keys_for(action).iter().map(|k| k.name()).collect();
+        names.join(", ")
+    }
+}
+
+pub struct MouseSettings {
+    pub look: bool,
+    pub sensitivity: f32,
+    pub invert_y: bool,
+}
+
+impl MouseSettings {
+    pub fn from_settings(settings: &Settings) -> MouseSettings {
+        MouseSettings {
+            look: settings.get_or("mouse.look", true),
+            sensitivity: settings.get_or("mouse.sensitivity", 0.1),
+            invert_y: settings.get_or("mouse.invert_y", false),
+        }
+    }
+}
+
+pub struct Input {
+    pub bindings: Bindings,
+    pub mouse: MouseSettings,
+    held: HashSet<Keycode>,
+    mouse_fire: bool,
+    mouse_motion: (i32, i32),
+}
+
+impl Input {
+    pub fn new(bindings: Bindings, mouse: MouseSettings) -> Input {
+        Input {
+            bindings,
+            mouse,
+            held: HashSet::new(),
+            mouse_fire: false,
+            mouse_motion: (0, 0),
+        }
+    }
+
+    /// Returns the actions the key triggers, repeats from a key being held down are not reported again
+    pub fn key_down(&mut self, key: Keycode, repeat: bool) -> Vec<Action> {
+        self.held.insert(key);
+        if repeat {
+            return Vec::new();
+        }
+        self.bindings.actions_for(key)
+    }
+
+    pub fn key_up(&mut self, key: Keycode) {
+        self.held.remove(&key);
+    }
+
+    pub fn mouse_down(&mut self, button: MouseButton) {
+        if button == MouseButton::Left {
+            self.mouse_fire = true;
+        }
+    }
+
+    pub fn mouse_up(&mut self, button: MouseButton) {
+        if button == MouseButton::Left {
+            self.mouse_fire = false;
+        }
+    }
+
+    pub fn mouse_moved(&mut self, xrel: i32, yrel: i32) {
+        if self.mouse.look {
+            self.mouse_motion = (self.mouse_motion.0 + xrel, self.mouse_motion.1 + yrel);
+        }
+    }
+
+    /// Mouse movement since the last call
+    pub fn take_mouse_motion(&mut self) -> (i32, i32) {
+        let motion = self.mouse_motion;
+        self.mouse_motion = (0, 0);
+        motion
+    }
+
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.mouse_fire = false;
+        self.mouse_motion = (0, 0);
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        if action == Action::Fire && self.mouse_fire {
+            return true;
+        }
+        self.bindings.keys_for(action).iter().any(|k| self.held.contains(k))
+    }
+}
+
+pub enum MenuResult {
+    Nothing,
+    Changed,
+    Closed,
+}
+
+/// Options menu for rebinding controls. Up/Down pick an action, Return waits for a key to add
+/// to it, Backspace clears its keys and Escape leaves the menu.
+pub struct OptionsMenu {
+    pub open: bool,
+    selected: usize,
+    waiting_for_key: bool,
+}
+
+impl OptionsMenu {
+    pub fn new() -> OptionsMenu {
+        OptionsMenu {
+            open: false,
+            selected: 0,
+            waiting_for_key: false,
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.open = true;
+        self.waiting_for_key = false;
+    }
+
+    pub fn handle_key(&mut self, key: Keycode, bindings: &mut Bindings) -> MenuResult {
+        let action = ALL_ACTIONS[self.selected];
+        if self.waiting_for_key {
+            self.waiting_for_key = false;
+            if key != Keycode::Escape && bindings.bind(action, key) {
+                MenuResult::Changed
+            } else {
+                MenuResult::Nothing
+            }
+        } else {
+            match key {
+                Keycode::Escape => {
+                    self.open = false;
+                    MenuResult::Closed
+                }
+                Keycode::Up => {
+                    self.selected = (self.selected + ALL_ACTIONS.len() - 1) % ALL_ACTIONS.len();
+                    MenuResult::Nothing
+                }
+                Keycode::Down => {
+                    self.selected = (self.selected + 1) % ALL_ACTIONS.len();
+                    MenuResult::Nothing
+                }
+                Keycode::Return => {
+                    self.waiting_for_key = true;
+                    MenuResult::Nothing
+                }
+                Keycode::Backspace | Keycode::Delete => {
+                    if bindings.clear(action) {
+                        MenuResult::Changed
+                    } else {
+                        MenuResult::Nothing
+                    }
+                }
+                _ => MenuResult::Nothing,
+            }
+        }
+    }
+
+    pub fn lines(&self, bindings: &Bindings) -> Vec<String> {
+        let mut lines = vec!["OPTIONS - up/down select, return add key, backspace clear, escape done".to_string()];
+        for (i, action) in ALL_ACTIONS.iter().enumerate() {
+            let marker = if i == self.selected { if self.waiting_for_key { "?" } else { ">" } } else { " " };
+            lines.push(format!("{} {:<10} {}", marker, action.name(), bindings.describe(*action)));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_with(entries: &[(&str, &str)]) -> Settings {
+        let mut settings = Settings::load("no-such-settings-file.cfg");
+        for (key, value) in entries {
+            settings.set(key, value.to_string());
+        }
+        settings
+    }
+
+    #[test]
+    fn bind_moves_a_key_off_other_actions() {
+        let mut bindings = Bindings::default();
+        assert!(bindings.bind(Action::Fire, Keycode::A));
+        assert_eq!(bindings.actions_for(Keycode::A), vec![Action::Fire]);
+        assert!(bindings.keys_for(Action::StrafeLeft).is_empty());
+        assert_eq!(bindings.keys_for(Action::Fire), &[Keycode::Space, Keycode::A]);
+    }
+
+    #[test]
+    fn bind_and_clear_keep_a_key_on_actions_that_need_one() {
+        let mut bindings = Bindings::default();
+        assert!(!bindings.bind(Action::Fire, Keycode::Escape));
+        assert_eq!(bindings.keys_for(Action::Quit), &[Keycode::Escape]);
+        assert!(!bindings.clear(Action::Pause));
+        assert_eq!(bindings.keys_for(Action::Pause), &[Keycode::P]);
+
+        assert!(bindings.bind(Action::Quit, Keycode::Q));
+        assert!(bindings.bind(Action::Fire, Keycode::Escape));
+        assert_eq!(bindings.keys_for(Action::Quit), &[Keycode::Q]);
+        assert!(bindings.clear(Action::Fire));
+        assert!(bindings.keys_for(Action::Fire).is_empty());
+    }
+
+    #[test]
+    fn from_settings_reads_keys_and_keeps_defaults() {
+        let bindings = Bindings::from_settings(&settings_with(&[("bind.Fire", "Space, Return, Nonsense")]));
+        assert_eq!(bindings.keys_for(Action::Fire), &[Keycode::Space, Keycode::Return]);
+        assert_eq!(bindings.keys_for(Action::TurnLeft), &[Keycode::Left]);
+    }
+
+    #[test]
+    fn from_settings_gives_a_key_bound_twice_to_the_first_action() {
+        let bindings = Bindings::from_settings(&settings_with(&[("bind.TurnLeft", "Left, X"), ("bind.Fire", "X, Space")]));
+        assert_eq!(bindings.keys_for(Action::TurnLeft), &[Keycode::Left, Keycode::X]);
+        assert_eq!(bindings.keys_for(Action::Fire), &[Keycode::Space]);
+        assert_eq!(bindings.actions_for(Keycode::X), vec![Action::TurnLeft]);
+    }
+
+    #[test]
+    fn from_settings_takes_old_keys_off_new_defaults() {
+        // a settings.cfg saved before strafing had A and D turning
+        let bindings = Bindings::from_settings(&settings_with(&[("bind.TurnLeft", "Left, A"), ("bind.TurnRight", "Right, D")]));
+        assert_eq!(bindings.actions_for(Keycode::A), vec![Action::TurnLeft]);
+        assert_eq!(bindings.actions_for(Keycode::D), vec![Action::TurnRight]);
+        assert!(bindings.keys_for(Action::StrafeLeft).is_empty());
+    }
+
+    #[test]
+    fn from_settings_restores_keys_that_are_needed() {
+        let bindings = Bindings::from_settings(&settings_with(&[("bind.Quit", ""), ("bind.Fire", "P")]));
+        assert_eq!(bindings.keys_for(Action::Quit), &[Keycode::Escape]);
+        assert_eq!(bindings.keys_for(Action::Pause), &[Keycode::P]);
+        assert_eq!(bindings.keys_for(Action::Fire), &[Keycode::Space]);
+    }
+
+    #[test]
+    fn write_to_round_trips() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Action::Fire, Keycode::Return);
+        let mut settings = settings_with(&[]);
+        bindings.write_to(&mut settings);
+        let read = Bindings::from_settings(&settings);
+        for action in ALL_ACTIONS.iter() {
+            assert_eq!(read.keys_for(*action), bindings.keys_for(*action));
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::settings::Settings;
+use crate::skybox::Sky;
+
+pub const LEVELS_FILE: &str = "resources/levels.cfg";
+
+/// What changes from wave to wave, read from LEVELS_FILE. Waves after the last one listed go
+/// round the list again.
+pub struct Levels {
+    skies: Vec<Sky>,
+}
+
+impl Levels {
+    /// level.1.sky, level.2.sky and so on until one is missing
+    pub fn load(filename: &str) -> Levels {
+        let file = Settings::load(filename);
+        let mut skies = Vec::new();
+        while let Some(sky) = file.get(&format!("level.{}.sky", skies.len() + 1)) {
+            skies.push(sky.parse().unwrap_or(Sky::Stars));
+        }
+        if skies.is_empty() {
+            skies.push(Sky::Stars);
+        }
+        Levels {
+            skies,
+        }
+    }
+
+    /// Levels start at 1, 0 before the first wave has the first level's sky
+    pub fn sky(&self, level: i32) -> &Sky {
+        &self.skies[(level.max(1) - 1) as usize % self.skies.len()]
+    }
+}
@@ -11,6 +11,21 @@ mod alien_army;
 mod bullets;
 mod explosion;
 mod handle_javascript;
+mod stats;
+mod settings;
+mod input;
+mod difficulty;
+mod player;
+mod arena;
+mod view_camera;
+mod display;
+mod hud;
+mod audio;
+mod sfxr;
+mod music;
+mod radar;
+mod skybox;
+mod levels;
 
 pub const WIDTH:u32=800;
 pub const HEIGHT:u32=600;
@@ -27,6 +42,15 @@ pub fn get_next_id() -> u128 {
 }
 
 fn main() {
+    // rust-invaders --export-sounds <directory> writes every generated sound, music included, as WAV files
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 3 && args[1] == "--export-sounds" {
+        if let Err(e) = audio::export_sounds(&args[2]) {
+            println!("unable to export sounds {}", e);
+        }
+        return;
+    }
+
     let runtime = Runtime::new();
 
     emscripten_main_loop::run(runtime);
@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+use sdl2::mixer::Chunk;
+
+use crate::alien_army::AlienArmy;
+use crate::audio::{Audio, MUSIC_LAYERS};
+use crate::game::ROW_SIZE;
+use crate::sfxr::SfxParams;
+
+/// The four falling notes of the march, G F E D
+const MARCH_NOTES: [f32; 4] = [98.0, 87.31, 82.41, 73.42];
+/// Seconds between notes when a wave starts and when it is about to land
+const SLOWEST_BEAT: f32 = 0.9;
+const FASTEST_BEAT: f32 = 0.16;
+/// Seconds to fade from one layer to another
+const CROSSFADE: f32 = 2.0;
+/// Pressure where the danger layer starts to come in and where it has taken over
+const DANGER_START: f32 = 0.35;
+const DANGER_FULL: f32 = 0.6;
+/// The boss layer plays for the last few aliens of a wave
+const LAST_STAND: usize = 3;
+
+const LAYER_FILES: [&str; MUSIC_LAYERS as usize] = [
+    "resources/sounds/music_calm.sfx",
+    "resources/sounds/music_danger.sfx",
+    "resources/sounds/music_boss.sfx",
+];
+const CALM: usize = 0;
+const DANGER: usize = 1;
+const BOSS: usize = 2;
+
+/// Background march that speeds up as the army gets lower and fewer aliens are left, the way
+/// the arcade game does. Each layer plays the same notes with a different sound, and the layers
+/// fade in and out with how much trouble the player is in.
+pub struct AdaptiveMusic {
+    /// notes for each layer, empty if there is no audio
+    layers: Vec<Vec<Chunk>>,
+    weights: [f32; MUSIC_LAYERS as usize],
+    note: usize,
+    until_next_note: f32,
+    last_update: Instant,
+}
+
+impl AdaptiveMusic {
+    pub fn new(audio: &Audio) -> AdaptiveMusic {
+        let layers = LAYER_FILES.iter().map(|file| {
+            let mut params = SfxParams::from_file(file);
+            MARCH_NOTES.iter().filter_map(|hertz| {
+                params.base_freq = SfxParams::base_freq_for(*hertz);
+                audio.synthesize(&params, file)
+            }).collect()
+        }).collect();
+        AdaptiveMusic {
+            layers,
+            weights: [1.0, 0.0, 0.0],
+            note: 0,
+            until_next_note: 0.0,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// 0 when a wave has just started, 1 when it is nearly on the ground or nearly all shot
+    pub fn pressure(army: &AlienArmy) -> f32 {
+        let wave_size = (ROW_SIZE * ROW_SIZE) as f32;
+        let shot = 1.0 - (army.remaining() as f32 / wave_size).min(1.0);
+        (army.descent() * 0.6 + shot * 0.4).min(1.0)
+    }
+
+    fn target_weights(army: &AlienArmy, pressure: f32) -> [f32; MUSIC_LAYERS as usize] {
+        let mut weights = [0.0; MUSIC_LAYERS as usize];
+        let remaining = army.remaining();
+        if remaining > 0 && remaining <= LAST_STAND {
+            weights[BOSS] = 1.0;
+        } else {
+            let danger = ((pressure - DANGER_START) / (DANGER_FULL - DANGER_START)).clamp(0.0, 1.0);
+            weights[CALM] = 1.0 - danger;
+            weights[DANGER] = danger;
+        }
+        weights
+    }
+
+    /// Called every frame the game is running, plays the next note when it is due
+    pub fn update(&mut self, audio: &Audio, army: &AlienArmy) {
+        // not called while paused or in the options menu, so the gap after one is cut short and
+        // the march picks up where it stopped instead of rushing through the missed beats
+        let seconds = self.last_update.elapsed().as_secs_f32().min(0.1);
+        self.last_update = Instant::now();
+
+        let pressure = AdaptiveMusic::pressure(army);
+        let target = AdaptiveMusic::target_weights(army, pressure);
+        let step = seconds / CROSSFADE;
+        for (layer, weight) in self.weights.iter_mut().enumerate() {
+            *weight = *weight + (target[layer] - *weight).clamp(-step, step);
+            audio.set_music_layer_weight(layer as i32, *weight);
+        }
+
+        self.until_next_note = self.until_next_note - seconds;
+        if self.until_next_note > 0.0 {
+            return;
+        }
+        self.until_next_note = SLOWEST_BEAT + (FASTEST_BEAT - SLOWEST_BEAT) * pressure;
+        for (layer, notes) in self.layers.iter().enumerate() {
+            if self.weights[layer] > 0.0 {
+                if let Some(chunk) = notes.get(self.note) {
+                    audio.play_music_layer(layer as i32, chunk, self.weights[layer]);
+                }
+            }
+        }
+        self.note = (self.note + 1) % MARCH_NOTES.len();
+    }
+}
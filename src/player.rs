@@ -0,0 +1,93 @@
+use cgmath::{vec3, Vector3, Point3, InnerSpace, EuclideanSpace, Zero};
+
+use crate::arena::{keep_in_arena, slide_off_sphere, ARENA_HEIGHT};
+use crate::difficulty::Difficulty;
+use crate::flying_camera::Flying_Camera;
+use crate::input::{Action, Input};
+use crate::settings::Settings;
+
+pub const EYE_HEIGHT: f32 = 0.05;
+pub const PLAYER_RADIUS: f32 = 0.07;
+const DECELERATION: f32 = 0.06;
+
+/// Moves the player with a speed that ramps up while a movement key is held and
+/// bleeds off when it is let go
+pub struct PlayerController {
+    pub velocity: Vector3<f32>,
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub vertical_flight: bool,
+}
+
+impl PlayerController {
+    pub fn new(difficulty: Difficulty, vertical_flight: bool) -> PlayerController {
+        PlayerController {
+            velocity: Vector3::zero(),
+            max_speed: difficulty.player_max_speed(),
+            acceleration: difficulty.player_acceleration(),
+            vertical_flight,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> PlayerController {
+        PlayerController::new(settings.get_or("difficulty", Difficulty::Normal),
+                              settings.get_or("player.vertical_flight", false))
+    }
+
+    /// Direction the player wants to go in, not normalised
+    fn wanted_direction(&self, camera: &Flying_Camera, input: &Input) -> Vector3<f32> {
+        let forward = if self.vertical_flight { camera.Front } else { camera.direction };
+        let right = vec3(camera.Right.x, 0.0, camera.Right.z);
+        let mut wanted = Vector3::zero();
+        if input.held(Action::Forward) { wanted += forward; }
+        if input.held(Action::Backward) { wanted -= forward; }
+        if input.held(Action::StrafeRight) { wanted += right; }
+        if input.held(Action::StrafeLeft) { wanted -= right; }
+        if self.vertical_flight {
+            if input.held(Action::Ascend) { wanted += camera.WorldUp; }
+            if input.held(Action::Descend) { wanted -= camera.WorldUp; }
+        }
+        wanted
+    }
+
+    pub fn update(&mut self, camera: &mut Flying_Camera, input: &Input, delta: f32) {
+        let wanted = self.wanted_direction(camera, input);
+        if wanted.magnitude2() > 0.0 {
+            let target = wanted.normalize() * self.max_speed;
+            let change = target - self.velocity;
+            let step = self.acceleration * delta;
+            if change.magnitude() <= step {
+                self.velocity = target;
+            } else {
+                self.velocity += change.normalize() * step;
+            }
+        } else {
+            let speed = self.velocity.magnitude();
+            let step = DECELERATION * delta;
+            if speed <= step {
+                self.velocity = Vector3::zero();
+            } else {
+                self.velocity -= self.velocity.normalize() * step;
+            }
+        }
+
+        let mut position = camera.Position.to_vec() + self.velocity * delta;
+        keep_in_arena(&mut position, &mut self.velocity, PLAYER_RADIUS);
+        if self.vertical_flight {
+            position.y = position.y.clamp(EYE_HEIGHT, ARENA_HEIGHT - PLAYER_RADIUS);
+        } else {
+            position.y = EYE_HEIGHT;
+        }
+        camera.Position = Point3::from_vec(position);
+    }
+
+    /// Slides the player around a sphere it bumps into, when not flying only the ground position is changed
+    pub fn collide_with(&mut self, camera: &mut Flying_Camera, mut centre: Vector3<f32>, radius: f32) {
+        let mut position = camera.Position.to_vec();
+        if !self.vertical_flight {
+            centre.y = position.y;
+        }
+        slide_off_sphere(&mut position, &mut self.velocity, centre, PLAYER_RADIUS + radius);
+        camera.Position = Point3::from_vec(position);
+    }
+}
@@ -0,0 +1,147 @@
+use cgmath::{vec2, vec3, vec4, Matrix4, Rad, Vector2, Vector4, SquareMatrix, InnerSpace};
+
+use crate::arena::ARENA_HALF_SIZE;
+use crate::flying_camera::Flying_Camera;
+use crate::game::MovingAlien;
+use crate::gl;
+use crate::gl_helper::sprite::Sprite;
+use crate::gl_helper::assets::Assets;
+
+const DISC_SIZE: u32 = 64;
+const ARROW_SIZE: u32 = 32;
+/// Diving aliens lower than this get an arrow at the edge of the screen when they are out of view
+const WARNING_HEIGHT: f32 = 0.15;
+
+const CROSSHAIR_COLOUR: Vector4<f32> = Vector4 { x: 0.3, y: 1.0, z: 0.3, w: 0.8 };
+const RADAR_COLOUR: Vector4<f32> = Vector4 { x: 0.2, y: 0.6, z: 0.2, w: 0.6 };
+const MARCHING_COLOUR: Vector4<f32> = Vector4 { x: 0.3, y: 1.0, z: 0.3, w: 1.0 };
+const DIVING_COLOUR: Vector4<f32> = Vector4 { x: 1.0, y: 0.2, z: 0.2, w: 1.0 };
+const LANDED_COLOUR: Vector4<f32> = Vector4 { x: 0.5, y: 0.5, z: 0.5, w: 0.8 };
+
+/// Filled circle with a brighter rim, alpha only so it can be tinted
+fn disc_pixels(size: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    let centre = (size as f32 - 1.0) / 2.0;
+    for y in 0..size {
+        for x in 0..size {
+            let distance = vec2(x as f32 - centre, y as f32 - centre).magnitude() / centre;
+            let alpha = if distance > 1.0 { 0 } else if distance > 0.92 { 255 } else { 70 };
+            pixels.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+    pixels
+}
+
+/// Triangle pointing to the top of the texture
+fn arrow_pixels(size: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        // rows are bottom up, so the point is on the last row
+        let half_width = (size - y) as f32 / 2.0;
+        for x in 0..size {
+            let inside = (x as f32 + 0.5 - size as f32 / 2.0).abs() <= half_width;
+            pixels.extend_from_slice(&[255, 255, 255, if inside { 255 } else { 0 }]);
+        }
+    }
+    pixels
+}
+
+/// Crosshair, radar showing the aliens around the player and arrows to low diving aliens that are out of view.
+/// Drawn in the HUD pass so coordinates are in pixels from the top left.
+pub struct Radar {
+    square: Sprite,
+    disc: Sprite,
+    arrow: Sprite,
+}
+
+impl Radar {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Result<Radar, String> {
+        let white = assets.white(gl);
+        let disc = assets.rgba_texture(gl, "radar disc", DISC_SIZE, DISC_SIZE, || disc_pixels(DISC_SIZE));
+        let arrow = assets.rgba_texture(gl, "radar arrow", ARROW_SIZE, ARROW_SIZE, || arrow_pixels(ARROW_SIZE));
+        Ok(Radar {
+            square: Sprite::new(gl, assets, 0.0, 0.0, "", 1.0, 1.0, Some(white))?,
+            disc: Sprite::new(gl, assets, 0.0, 0.0, "", 1.0, 1.0, Some(disc))?,
+            arrow: Sprite::new(gl, assets, 0.0, 0.0, "", 1.0, 1.0, Some(arrow))?,
+        })
+    }
+
+    fn draw(sprite: &mut Sprite, gl: &gl::Gl, projection: &Matrix4<f32>, centre: Vector2<f32>, size: Vector2<f32>, angle: f32, colour: Vector4<f32>) {
+        sprite.tint = colour;
+        sprite.transform = Matrix4::from_translation(vec3(centre.x, centre.y, 0.0)) *
+            Matrix4::from_angle_z(Rad(angle)) *
+            Matrix4::from_nonuniform_scale(size.x, -size.y, 1.0);
+        sprite.render_with(gl, &Matrix4::identity(), projection);
+    }
+
+    pub fn render_crosshair(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, viewport: (u32, u32), scale: f32) {
+        let centre = vec2(viewport.0 as f32 / 2.0, viewport.1 as f32 / 2.0);
+        let (gap, length, thickness) = (4.0 * scale, 8.0 * scale, (1.5 * scale).round().max(1.0));
+        for (dx, dy) in [(1.0f32, 0.0f32), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)].iter() {
+            let offset = vec2(*dx, *dy) * (gap + length / 2.0);
+            let size = if *dx != 0.0 { vec2(length, thickness) } else { vec2(thickness, length) };
+            Radar::draw(&mut self.square, gl, projection, centre + offset, size, 0.0, CROSSHAIR_COLOUR);
+        }
+    }
+
+    /// Radar in the bottom left corner, the direction the player faces is up
+    pub fn render_radar(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, viewport: (u32, u32), scale: f32, player: &Flying_Camera, aliens: &[MovingAlien]) {
+        let radius = 48.0 * scale;
+        let margin = 8.0 * scale;
+        let centre = vec2(margin + radius, viewport.1 as f32 - margin * 4.0 - radius);
+        Radar::draw(&mut self.disc, gl, projection, centre, vec2(radius * 2.0, radius * 2.0), 0.0, RADAR_COLOUR);
+        Radar::draw(&mut self.arrow, gl, projection, centre, vec2(6.0 * scale, 8.0 * scale), 0.0, CROSSHAIR_COLOUR);
+
+        let forward = vec2(player.direction.x, player.direction.z);
+        let right = vec2(-forward.y, forward.x);
+        // the whole arena fits across the radar wherever the player is
+        let to_radar = radius / (ARENA_HALF_SIZE * 2.0);
+        for alien in aliens {
+            let offset = vec2(alien.movement_collision.position.x - player.Position.x,
+                              alien.movement_collision.position.z - player.Position.z);
+            let mut blip = vec2(offset.dot(right), -offset.dot(forward)) * to_radar;
+            if blip.magnitude() > radius - 2.0 * scale {
+                blip = blip.normalize() * (radius - 2.0 * scale);
+            }
+            let (colour, size) = if alien.landed {
+                (LANDED_COLOUR, 3.0 * scale)
+            } else if alien.drop_down {
+                (DIVING_COLOUR, 5.0 * scale)
+            } else {
+                (MARCHING_COLOUR, 3.0 * scale)
+            };
+            Radar::draw(&mut self.square, gl, projection, centre + blip, vec2(size, size), 0.0, colour);
+        }
+    }
+
+    /// Arrows round the edge of the screen pointing at diving aliens close to the ground that can not be seen
+    /// view_projection takes world positions to the scene's clip space
+    pub fn render_warnings(&mut self, gl: &gl::Gl, projection: &Matrix4<f32>, viewport: (u32, u32), scale: f32,
+                           view_projection: &Matrix4<f32>, aliens: &[MovingAlien]) {
+        let half = vec2(viewport.0 as f32 / 2.0, viewport.1 as f32 / 2.0);
+        let margin = 24.0 * scale;
+        for alien in aliens.iter().filter(|a| a.drop_down && !a.landed && a.movement_collision.position.y < WARNING_HEIGHT) {
+            let p = alien.movement_collision.position;
+            let clip = view_projection * vec4(p.x, p.y, p.z, 1.0);
+            let mut direction = vec2(clip.x, -clip.y);
+            if clip.w > 0.0 {
+                direction = direction / clip.w;
+                if direction.x.abs() <= 1.0 && direction.y.abs() <= 1.0 {
+                    continue;
+                }
+            } else {
+                // behind the player, the projection mirrors it
+                direction = -direction;
+            }
+            if direction.magnitude2() == 0.0 {
+                direction = vec2(0.0, 1.0);
+            }
+            let pixels = vec2(direction.x * half.x, direction.y * half.y).normalize();
+            let reach_x = if pixels.x != 0.0 { (half.x - margin) / pixels.x.abs() } else { f32::MAX };
+            let reach_y = if pixels.y != 0.0 { (half.y - margin) / pixels.y.abs() } else { f32::MAX };
+            let at = half + pixels * reach_x.min(reach_y);
+            let angle = pixels.x.atan2(-pixels.y);
+            Radar::draw(&mut self.arrow, gl, projection, at, vec2(16.0 * scale, 16.0 * scale), angle, DIVING_COLOUR);
+        }
+    }
+}
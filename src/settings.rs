@@ -0,0 +1,131 @@
+use std::fs;
+use std::str::FromStr;
+
+pub const SETTINGS_FILE: &str = "settings.cfg";
+
+/// Simple "key = value" settings file. Lines starting with # are comments, the order
+/// of entries is kept when saving but comments are not.
+pub struct Settings {
+    filename: String,
+    values: Vec<(String, String)>,
+}
+
+impl Settings {
+    pub fn load(filename: &str) -> Settings {
+        let mut values = Vec::new();
+        match fs::read_to_string(filename) {
+            Ok(contents) => {
+                for line in contents.lines() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    match line.find('=') {
+                        Some(at) => {
+                            let key = line[..at].trim().to_string();
+                            let value = line[at + 1..].trim().to_string();
+                            values.push((key, value));
+                        }
+                        None => println!("{} ignoring line without = '{}'", filename, line),
+                    }
+                }
+            }
+            Err(_) => println!("no {} found, using defaults", filename),
+        }
+        Settings {
+            filename: filename.to_string(),
+            values,
+        }
+    }
+
+    pub fn save(&self) {
+        let mut contents = String::from("# rust-invaders settings\n");
+        for (key, value) in &self.values {
+            contents.push_str(&format!("{} = {}\n", key, value));
+        }
+        if let Err(e) = fs::write(&self.filename, contents) {
+            println!("unable to save {} {}", self.filename, e);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Parses the value for a key, falling back to the default if it is missing or not valid
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Some(value) => match value.parse() {
+                Ok(parsed) => parsed,
+                Err(_) => {
+                    println!("{} invalid value '{}' for {}", self.filename, value, key);
+                    default
+                }
+            },
+            None => default,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: String) {
+        match self.values.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.values.push((key.to_string(), value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn write_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("rust-invaders-{}-{}.cfg", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn parses_entries_and_skips_comments() {
+        let path = write_file("parse", "# comment\n\n  difficulty = hard  \nno equals here\nbind.Fire = Space, Return\n");
+        let settings = Settings::load(&path);
+        assert_eq!(settings.get("difficulty"), Some("hard"));
+        assert_eq!(settings.get("bind.Fire"), Some("Space, Return"));
+        assert_eq!(settings.get("no equals here"), None);
+        assert_eq!(settings.get("# comment"), None);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn get_or_falls_back_when_missing_or_invalid() {
+        let path = write_file("get_or", "mouse.sensitivity = 0.5\nmouse.look = maybe\n");
+        let settings = Settings::load(&path);
+        assert_eq!(settings.get_or("mouse.sensitivity", 0.1), 0.5);
+        assert!(settings.get_or("mouse.look", true));
+        assert_eq!(settings.get_or("display.width", 800u32), 800);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn save_keeps_entries_in_order() {
+        let path = write_file("save", "b = 2\na = 1\n");
+        let mut settings = Settings::load(&path);
+        settings.set("a", "3".to_string());
+        settings.set("c", "4".to_string());
+        settings.save();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        let entries: Vec<&str> = saved.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(entries, vec!["b = 2", "a = 3", "c = 4"]);
+        let reloaded = Settings::load(&path);
+        assert_eq!(reloaded.values, settings.values);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn missing_file_has_no_entries() {
+        let settings = Settings::load("no-such-settings-file.cfg");
+        assert_eq!(settings.get("difficulty"), None);
+        assert_eq!(settings.get_or("difficulty", 2), 2);
+    }
+}
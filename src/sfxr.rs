@@ -0,0 +1,343 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::str::FromStr;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::settings::Settings;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Each output sample is the average of this many, which takes the edge off the square and noise waves
+const SUPERSAMPLE: usize = 8;
+const NOISE_SAMPLES: usize = 32;
+/// Stops a sound with a long sustain and no frequency limit running on for ever
+const MAX_SECONDS: u32 = 5;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Wave {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+impl FromStr for Wave {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(Wave::Square),
+            "sawtooth" => Ok(Wave::Sawtooth),
+            "sine" => Ok(Wave::Sine),
+            "noise" => Ok(Wave::Noise),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The parameters of an sfxr sound. They use the same ranges as sfxr, 0 to 1 or -1 to 1 for the
+/// ramps, so sounds made with sfxr can be copied across.
+pub struct SfxParams {
+    pub wave: Wave,
+    pub base_freq: f32,
+    /// Frequency the slide stops at, the sound ends when it gets there
+    pub freq_limit: f32,
+    pub freq_ramp: f32,
+    pub freq_dramp: f32,
+    /// Square wave duty cycle
+    pub duty: f32,
+    pub duty_ramp: f32,
+    pub vib_strength: f32,
+    pub vib_speed: f32,
+    pub attack: f32,
+    pub sustain: f32,
+    pub punch: f32,
+    pub decay: f32,
+    /// Jump in frequency part way through, below 0 jumps down
+    pub arp_mod: f32,
+    pub arp_speed: f32,
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub lpf_resonance: f32,
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+    pub volume: f32,
+    /// Seed for the noise wave so a sound is the same every time it is made
+    pub seed: u64,
+}
+
+impl Default for SfxParams {
+    fn default() -> Self {
+        SfxParams {
+            wave: Wave::Square,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_dramp: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            vib_strength: 0.0,
+            vib_speed: 0.0,
+            attack: 0.0,
+            sustain: 0.3,
+            punch: 0.0,
+            decay: 0.4,
+            arp_mod: 0.0,
+            arp_speed: 0.0,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            volume: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+impl SfxParams {
+    /// Reads a "key = value" sound file, anything missing keeps the default
+    pub fn from_file(filename: &str) -> SfxParams {
+        let file = Settings::load(filename);
+        let default = SfxParams::default();
+        SfxParams {
+            wave: file.get_or("wave", default.wave),
+            base_freq: file.get_or("base_freq", default.base_freq),
+            freq_limit: file.get_or("freq_limit", default.freq_limit),
+            freq_ramp: file.get_or("freq_ramp", default.freq_ramp),
+            freq_dramp: file.get_or("freq_dramp", default.freq_dramp),
+            duty: file.get_or("duty", default.duty),
+            duty_ramp: file.get_or("duty_ramp", default.duty_ramp),
+            vib_strength: file.get_or("vib_strength", default.vib_strength),
+            vib_speed: file.get_or("vib_speed", default.vib_speed),
+            attack: file.get_or("attack", default.attack),
+            sustain: file.get_or("sustain", default.sustain),
+            punch: file.get_or("punch", default.punch),
+            decay: file.get_or("decay", default.decay),
+            arp_mod: file.get_or("arp_mod", default.arp_mod),
+            arp_speed: file.get_or("arp_speed", default.arp_speed),
+            lpf_freq: file.get_or("lpf_freq", default.lpf_freq),
+            lpf_ramp: file.get_or("lpf_ramp", default.lpf_ramp),
+            lpf_resonance: file.get_or("lpf_resonance", default.lpf_resonance),
+            hpf_freq: file.get_or("hpf_freq", default.hpf_freq),
+            hpf_ramp: file.get_or("hpf_ramp", default.hpf_ramp),
+            volume: file.get_or("volume", default.volume),
+            seed: file.get_or("seed", default.seed),
+        }
+    }
+
+    /// The base_freq that plays a note of this many hertz
+    pub fn base_freq_for(hertz: f32) -> f32 {
+        (hertz / (SAMPLE_RATE * SUPERSAMPLE as u32) as f32 * 100.0 - 0.001).max(0.0).sqrt()
+    }
+
+    /// Makes the sound as 16 bit mono samples at SAMPLE_RATE, this is the sfxr synthesizer
+    pub fn synthesize(&self) -> Vec<i16> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut noise: Vec<f32> = (0..NOISE_SAMPLES).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+
+        let mut fperiod = 100.0 / (self.base_freq * self.base_freq + 0.001);
+        let fmaxperiod = 100.0 / (self.freq_limit * self.freq_limit + 0.001);
+        let mut fslide = 1.0 - self.freq_ramp.powi(3) * 0.01;
+        let fdslide = -self.freq_dramp.powi(3) * 0.000_001;
+        let mut square_duty = 0.5 - self.duty * 0.5;
+        let square_slide = -self.duty_ramp * 0.000_05;
+
+        let arp_mod = if self.arp_mod >= 0.0 {
+            1.0 - self.arp_mod.powi(2) * 0.9
+        } else {
+            1.0 + self.arp_mod.powi(2) * 10.0
+        };
+        let mut arp_limit = if self.arp_speed >= 1.0 { 0 } else { ((1.0 - self.arp_speed).powi(2) * 20000.0 + 32.0) as u32 };
+
+        let mut fltp = 0.0;
+        let mut fltdp = 0.0;
+        let mut fltw = self.lpf_freq.powi(3) * 0.1;
+        let fltw_d = 1.0 + self.lpf_ramp * 0.0001;
+        let fltdmp = (5.0 / (1.0 + self.lpf_resonance.powi(2) * 20.0) * (0.01 + fltw)).min(0.8);
+        let mut fltphp = 0.0;
+        let mut flthp = self.hpf_freq.powi(2) * 0.1;
+        let flthp_d = 1.0 + self.hpf_ramp * 0.0003;
+
+        let mut vib_phase: f32 = 0.0;
+        let vib_speed = self.vib_speed.powi(2) * 0.01;
+        let vib_amp = self.vib_strength * 0.5;
+
+        let envelope = [
+            ((self.attack * self.attack * 100_000.0) as u32).max(1),
+            ((self.sustain * self.sustain * 100_000.0) as u32).max(1),
+            ((self.decay * self.decay * 100_000.0) as u32).max(1),
+        ];
+        let mut env_stage = 0;
+        let mut env_time = 0;
+
+        let mut phase = 0;
+        let mut samples = Vec::new();
+        for time in 0..SAMPLE_RATE * MAX_SECONDS {
+            if arp_limit != 0 && time >= arp_limit {
+                arp_limit = 0;
+                fperiod = fperiod * arp_mod;
+            }
+
+            fslide = fslide + fdslide;
+            fperiod = fperiod * fslide;
+            if fperiod > fmaxperiod {
+                fperiod = fmaxperiod;
+                if self.freq_limit > 0.0 {
+                    break;
+                }
+            }
+            let mut rfperiod = fperiod;
+            if vib_amp > 0.0 {
+                vib_phase = vib_phase + vib_speed;
+                rfperiod = fperiod * (1.0 + vib_phase.sin() * vib_amp);
+            }
+            let period = (rfperiod as usize).max(8);
+            square_duty = (square_duty + square_slide).max(0.0).min(0.5);
+
+            env_time = env_time + 1;
+            if env_time > envelope[env_stage] {
+                env_time = 0;
+                env_stage = env_stage + 1;
+                if env_stage == envelope.len() {
+                    break;
+                }
+            }
+            let progress = env_time as f32 / envelope[env_stage] as f32;
+            let env_vol = match env_stage {
+                0 => progress,
+                1 => 1.0 + (1.0 - progress) * 2.0 * self.punch,
+                _ => 1.0 - progress,
+            };
+
+            flthp = (flthp * flthp_d).max(0.000_01).min(0.1);
+
+            let mut ssample = 0.0;
+            for _ in 0..SUPERSAMPLE {
+                phase = phase + 1;
+                if phase >= period {
+                    phase = phase % period;
+                    if self.wave == Wave::Noise {
+                        for n in noise.iter_mut() {
+                            *n = rng.gen_range(-1.0, 1.0);
+                        }
+                    }
+                }
+                let fp = phase as f32 / period as f32;
+                let sample = match self.wave {
+                    Wave::Square => if fp < square_duty { 0.5 } else { -0.5 },
+                    Wave::Sawtooth => 1.0 - fp * 2.0,
+                    Wave::Sine => (fp * 2.0 * PI).sin(),
+                    Wave::Noise => noise[phase * NOISE_SAMPLES / period],
+                };
+
+                // low pass then high pass filter
+                let pp = fltp;
+                fltw = (fltw * fltw_d).max(0.0).min(0.1);
+                if self.lpf_freq < 1.0 {
+                    fltdp = fltdp + (sample - fltp) * fltw;
+                    fltdp = fltdp - fltdp * fltdmp;
+                } else {
+                    fltp = sample;
+                    fltdp = 0.0;
+                }
+                fltp = fltp + fltdp;
+                fltphp = fltphp + fltp - pp;
+                fltphp = fltphp - fltphp * flthp;
+
+                ssample = ssample + fltphp * env_vol;
+            }
+            // sfxr is a lot quieter, here a square wave at volume 0.5 is about half of full scale
+            let ssample = ssample / SUPERSAMPLE as f32 * 2.0 * self.volume;
+            samples.push((ssample.max(-1.0).min(1.0) * i16::MAX as f32) as i16);
+        }
+        samples
+    }
+}
+
+/// 16 bit mono PCM WAV file
+pub fn wav_bytes(samples: &[i16]) -> Vec<u8> {
+    let data_length = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_length as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_length).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn write_wav(filename: &str, samples: &[i16]) -> Result<(), String> {
+    fs::write(filename, wav_bytes(samples)).map_err(|e| format!("{} {}", filename, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    #[test]
+    fn wav_header_describes_16_bit_mono() {
+        let samples = [0i16, 1, -1, i16::MAX, i16::MIN];
+        let wav = wav_bytes(&samples);
+        assert_eq!(wav.len(), 44 + samples.len() * 2);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4), wav.len() as u32 - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), 1);
+        assert_eq!(u16_at(&wav, 22), 1);
+        assert_eq!(u32_at(&wav, 24), SAMPLE_RATE);
+        assert_eq!(u32_at(&wav, 28), SAMPLE_RATE * 2);
+        assert_eq!(u16_at(&wav, 32), 2);
+        assert_eq!(u16_at(&wav, 34), 16);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), samples.len() as u32 * 2);
+        assert_eq!(u16_at(&wav, 44 + 3 * 2) as i16, i16::MAX);
+        assert_eq!(u16_at(&wav, 44 + 4 * 2) as i16, i16::MIN);
+    }
+
+    #[test]
+    fn wav_of_no_samples_is_just_the_header() {
+        let wav = wav_bytes(&[]);
+        assert_eq!(wav.len(), 44);
+        assert_eq!(u32_at(&wav, 4), 36);
+        assert_eq!(u32_at(&wav, 40), 0);
+    }
+
+    #[test]
+    fn same_parameters_make_the_same_sound() {
+        let noise = SfxParams { wave: Wave::Noise, seed: 7, ..SfxParams::default() };
+        let first = noise.synthesize();
+        assert!(!first.is_empty());
+        assert!(first.len() <= (SAMPLE_RATE * MAX_SECONDS) as usize);
+        assert_eq!(first, noise.synthesize());
+        assert_eq!(SfxParams::default().synthesize(), SfxParams::default().synthesize());
+    }
+
+    #[test]
+    fn noise_changes_with_the_seed() {
+        let first = SfxParams { wave: Wave::Noise, seed: 1, ..SfxParams::default() }.synthesize();
+        let second = SfxParams { wave: Wave::Noise, seed: 2, ..SfxParams::default() }.synthesize();
+        assert_eq!(first.len(), second.len());
+        assert_ne!(first, second);
+    }
+}
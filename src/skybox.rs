@@ -0,0 +1,262 @@
+use std::mem;
+use std::os::raw::c_void;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Instant;
+
+use cgmath::{vec3, InnerSpace, Vector3};
+
+use crate::gl;
+use crate::gl_helper::instancing::count_draw_call;
+use crate::gl_helper::shader::ShaderProgram;
+use crate::gl_helper::assets::Assets;
+
+const SKY_VERTEX_SHADER_SOURCE: &str = "#version 300 es
+layout (location = 0) in vec3 aPos;
+
+out vec3 Direction;
+
+layout (std140) uniform Camera {
+	mat4 view;
+	mat4 projection;
+};
+
+void main()
+{
+	Direction = aPos;
+	// only the rotation of the view, the sky is always the same distance away
+	vec4 pos = projection * mat4(mat3(view)) * vec4(aPos, 1.0);
+	gl_Position = pos.xyww;
+}
+";
+
+const CUBEMAP_FRAGMENT_SHADER_SOURCE: &str = "#version 300 es
+precision mediump float;
+out vec4 FragColor;
+
+in vec3 Direction;
+
+uniform samplerCube sky;
+
+void main()
+{
+	FragColor = texture(sky, Direction);
+}
+";
+
+const PROCEDURAL_FRAGMENT_SHADER_SOURCE: &str = "#version 300 es
+precision highp float;
+out vec4 FragColor;
+
+in vec3 Direction;
+
+uniform float time;
+uniform int showPlanet;
+uniform vec3 planetDirection;
+uniform vec3 sunDirection;
+
+float hash(vec3 p)
+{
+	p = fract(p * 0.3183099 + 0.1);
+	p *= 17.0;
+	return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// one star in some of the cells a grid cuts the sky into, twinkling at its own rate
+float stars(vec3 dir, float cells, float density)
+{
+	vec3 p = dir * cells;
+	vec3 cell = floor(p);
+	float chance = hash(cell);
+	if (chance > density) {
+		return 0.0;
+	}
+	vec3 star = cell + vec3(hash(cell + 1.7), hash(cell + 4.3), hash(cell + 9.1));
+	float size = 0.04 + 0.08 * hash(cell + 2.9);
+	float glow = 1.0 - smoothstep(0.0, size, length(p - star));
+	float twinkle = 0.65 + 0.35 * sin(time * (1.0 + 3.0 * hash(cell + 6.1)) + chance * 40.0);
+	return glow * twinkle;
+}
+
+float noise(vec3 p)
+{
+	vec3 i = floor(p);
+	vec3 f = fract(p);
+	f = f * f * (3.0 - 2.0 * f);
+	return mix(mix(mix(hash(i), hash(i + vec3(1, 0, 0)), f.x),
+	               mix(hash(i + vec3(0, 1, 0)), hash(i + vec3(1, 1, 0)), f.x), f.y),
+	           mix(mix(hash(i + vec3(0, 0, 1)), hash(i + vec3(1, 0, 1)), f.x),
+	               mix(hash(i + vec3(0, 1, 1)), hash(i + vec3(1, 1, 1)), f.x), f.y), f.z);
+}
+
+// a banded gas giant, lit from the sun with the bands drifting round
+vec4 planet(vec3 dir)
+{
+	float radius = 0.25;
+	vec3 centre = normalize(planetDirection);
+	vec3 right = normalize(cross(centre, vec3(0.0, 1.0, 0.0)));
+	vec3 up = cross(right, centre);
+	vec2 disc = vec2(dot(dir, right), dot(dir, up)) / radius;
+	float r2 = dot(disc, disc);
+	if (dot(dir, centre) < 0.0 || r2 > 1.0) {
+		return vec4(0.0);
+	}
+	vec3 normal = normalize(disc.x * right + disc.y * up - sqrt(1.0 - r2) * centre);
+	float latitude = disc.y;
+	float swirl = noise(vec3(disc.x * 3.0 + time * 0.02, latitude * 12.0, 0.0));
+	float bands = 0.5 + 0.5 * sin(latitude * 18.0 + swirl * 3.0);
+	vec3 colour = mix(vec3(0.75, 0.55, 0.35), vec3(0.95, 0.85, 0.65), bands);
+	float light = max(dot(normal, -normalize(sunDirection)), 0.0);
+	return vec4(colour * (0.03 + 0.97 * light), 1.0 - smoothstep(0.97, 1.0, r2));
+}
+
+void main()
+{
+	vec3 dir = normalize(Direction);
+	float glow = noise(dir * 3.0 + 7.0);
+	vec3 colour = vec3(0.01, 0.01, 0.03) + vec3(0.04, 0.02, 0.08) * glow * glow;
+	colour += vec3(stars(dir, 60.0, 0.25) + stars(dir, 150.0, 0.15) * 0.6);
+	if (showPlanet == 1) {
+		vec4 p = planet(dir);
+		colour = mix(colour, p.rgb, p.a);
+	}
+	FragColor = vec4(colour, 1.0);
+}
+";
+
+/// Where the planet hangs in the sky, low over the horizon
+const PLANET_DIRECTION: Vector3<f32> = Vector3 { x: 0.6, y: 0.25, z: -0.75 };
+
+/// What is drawn behind everything
+#[derive(PartialEq, Clone, Debug)]
+pub enum Sky {
+    /// Twinkling procedural stars
+    Stars,
+    /// Procedural stars with a planet
+    Planet,
+    /// A directory with the six faces of a cube map in it
+    Cubemap(String),
+}
+
+impl FromStr for Sky {
+    type Err = ();
+
+    /// stars, planet, or anything else is a cube map directory
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "" => Err(()),
+            "stars" => Ok(Sky::Stars),
+            "planet" => Ok(Sky::Planet),
+            _ => Ok(Sky::Cubemap(s.to_string())),
+        }
+    }
+}
+
+pub struct Skybox {
+    vao: u32,
+    cubemap_shader: Rc<ShaderProgram>,
+    procedural_shader: Rc<ShaderProgram>,
+    sky: Sky,
+    /// The cube map of the current sky, None for the procedural ones
+    cubemap: Option<u32>,
+    start: Instant,
+}
+
+impl Skybox {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> Skybox {
+        // a cube around the eye, the inside faces are what is seen
+        let corners = [
+            vec3(-1.0, -1.0, -1.0), vec3(1.0, -1.0, -1.0), vec3(1.0, 1.0, -1.0), vec3(-1.0, 1.0, -1.0),
+            vec3(-1.0, -1.0, 1.0), vec3(1.0, -1.0, 1.0), vec3(1.0, 1.0, 1.0), vec3(-1.0, 1.0, 1.0),
+        ];
+        let faces: [usize; 36] = [
+            0, 1, 2, 2, 3, 0,
+            4, 6, 5, 6, 4, 7,
+            0, 3, 7, 7, 4, 0,
+            1, 5, 6, 6, 2, 1,
+            3, 2, 6, 6, 7, 3,
+            0, 4, 5, 5, 1, 0,
+        ];
+        let vertices: Vec<f32> = faces.iter().flat_map(|i| {
+            let corner: Vector3<f32> = corners[*i];
+            vec![corner.x, corner.y, corner.z]
+        }).collect();
+
+        let mut vao = 0;
+        unsafe {
+            let mut vbo = 0;
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+            gl.BindVertexArray(vao);
+            gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl.BufferData(gl::ARRAY_BUFFER,
+                          (vertices.len() * mem::size_of::<gl::types::GLfloat>()) as gl::types::GLsizeiptr,
+                          &vertices[0] as *const f32 as *const c_void,
+                          gl::STATIC_DRAW);
+            gl.VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * mem::size_of::<gl::types::GLfloat>() as i32, std::ptr::null());
+            gl.EnableVertexAttribArray(0);
+            gl.BindVertexArray(0);
+        }
+
+        Skybox {
+            vao,
+            cubemap_shader: assets.shader(gl, "sky cube map", SKY_VERTEX_SHADER_SOURCE, CUBEMAP_FRAGMENT_SHADER_SOURCE),
+            procedural_shader: assets.shader(gl, "sky", SKY_VERTEX_SHADER_SOURCE, PROCEDURAL_FRAGMENT_SHADER_SOURCE),
+            sky: Sky::Stars,
+            cubemap: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Changes the sky, cube maps are loaded the first time a level uses them and one that
+    /// can not be loaded falls back to the stars
+    pub fn set_sky(&mut self, gl: &gl::Gl, assets: &mut Assets, sky: &Sky) {
+        if *sky == self.sky {
+            return;
+        }
+        self.cubemap = None;
+        self.sky = sky.clone();
+        if let Sky::Cubemap(directory) = sky {
+            match assets.cubemap(gl, directory) {
+                Ok(texture) => self.cubemap = Some(texture),
+                Err(e) => {
+                    println!("unable to load sky {}, showing stars", e);
+                    self.sky = Sky::Stars;
+                }
+            }
+        }
+    }
+
+    /// Drawn first each frame, behind everything and without touching the depth buffer
+    pub fn render(&self, gl: &gl::Gl, sun_direction: Vector3<f32>) {
+        let shader = match self.cubemap {
+            Some(_) => &self.cubemap_shader,
+            None => &self.procedural_shader,
+        };
+        unsafe {
+            gl.Disable(gl::DEPTH_TEST);
+            gl.DepthMask(gl::FALSE);
+        }
+        shader.use_program(gl);
+        match self.cubemap {
+            Some(texture) => unsafe {
+                gl.ActiveTexture(gl::TEXTURE0);
+                gl.BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+            },
+            None => {
+                shader.set_float(gl, "time", self.start.elapsed().as_secs_f32());
+                shader.set_int(gl, "showPlanet", (self.sky == Sky::Planet) as i32);
+                shader.set_vector3(gl, "planetDirection", PLANET_DIRECTION.normalize());
+                shader.set_vector3(gl, "sunDirection", sun_direction);
+            }
+        }
+        count_draw_call();
+        unsafe {
+            gl.BindVertexArray(self.vao);
+            gl.DrawArrays(gl::TRIANGLES, 0, 36);
+            gl.BindVertexArray(0);
+            gl.DepthMask(gl::TRUE);
+            gl.Enable(gl::DEPTH_TEST);
+        }
+    }
+}
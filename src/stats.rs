@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::time::Instant;
+
+pub const STATS_SUMMARY_FILE: &str = "rust-invaders-stats.json";
+
+pub struct RunStats {
+    pub shots_fired: u32,
+    pub hits: u32,
+    pub kills: BTreeMap<&'static str, u32>,
+    pub landings: u32,
+    pub wave_times: Vec<f32>,
+    pub distance_travelled: f32,
+    wave_started: Option<Instant>,
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats {
+            shots_fired: 0,
+            hits: 0,
+            kills: BTreeMap::new(),
+            landings: 0,
+            wave_times: Vec::new(),
+            distance_travelled: 0.0,
+            wave_started: None,
+        }
+    }
+
+    pub fn shot_fired(&mut self) {
+        self.shots_fired = self.shots_fired + 1;
+    }
+
+    pub fn hit(&mut self) {
+        self.hits = self.hits + 1;
+    }
+
+    pub fn killed(&mut self, kind: &'static str) {
+        *self.kills.entry(kind).or_insert(0) += 1;
+    }
+
+    /// Closes the timer on the wave in progress, if any, and starts timing the next one
+    pub fn new_wave(&mut self) {
+        if let Some(started) = self.wave_started {
+            self.wave_times.push(started.elapsed().as_secs_f32());
+        }
+        self.wave_started = Some(Instant::now());
+    }
+
+    /// Percentage of shots that hit an alien
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.hits as f32 * 100.0 / self.shots_fired as f32
+        }
+    }
+
+    pub fn summary_line(&self, level: i32, score: i32) -> String {
+        format!("level {} score {} accuracy {:.0}%", level, score, self.accuracy())
+    }
+
+    pub fn to_json(&self, level: i32, score: i32) -> String {
+        let kills: Vec<String> = self.kills.iter()
+            .map(|(kind, count)| format!("\"{}\":{}", kind, count))
+            .collect();
+        let wave_times: Vec<String> = self.wave_times.iter()
+            .map(|t| format!("{:.2}", t))
+            .collect();
+        let current_wave = match self.wave_started {
+            Some(started) => started.elapsed().as_secs_f32(),
+            None => 0.0,
+        };
+        format!("{{\"level\":{},\"score\":{},\"shots_fired\":{},\"hits\":{},\"accuracy\":{:.1},\"kills\":{{{}}},\"landings\":{},\"wave_times\":[{}],\"current_wave_time\":{:.2},\"distance_travelled\":{:.3}}}",
+                level, score,
+                self.shots_fired, self.hits, self.accuracy(),
+                kills.join(","),
+                self.landings,
+                wave_times.join(","), current_wave,
+                self.distance_travelled)
+    }
+
+    pub fn write_summary(&self, filename: &str, level: i32, score: i32) {
+        let result = File::create(filename)
+            .and_then(|mut file| file.write_all(self.to_json(level, score).as_bytes()));
+        match result {
+            Ok(_) => println!("run summary written to {}", filename),
+            Err(e) => println!("unable to write run summary {} {}", filename, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_json_of_a_new_run() {
+        let stats = RunStats::new();
+        assert_eq!(stats.to_json(0, 0),
+                   "{\"level\":0,\"score\":0,\"shots_fired\":0,\"hits\":0,\"accuracy\":0.0,\"kills\":{},\"landings\":0,\"wave_times\":[],\"current_wave_time\":0.00,\"distance_travelled\":0.000}");
+    }
+
+    #[test]
+    fn to_json_with_shots_kills_and_waves() {
+        let mut stats = RunStats::new();
+        for _ in 0..4 {
+            stats.shot_fired();
+        }
+        stats.hit();
+        stats.killed("marching");
+        stats.killed("marching");
+        stats.killed("diving");
+        stats.landings = 2;
+        stats.wave_times = vec![12.5, 30.25];
+        stats.distance_travelled = 1.5;
+        assert_eq!(stats.to_json(3, -1),
+                   "{\"level\":3,\"score\":-1,\"shots_fired\":4,\"hits\":1,\"accuracy\":25.0,\"kills\":{\"diving\":1,\"marching\":2},\"landings\":2,\"wave_times\":[12.50,30.25],\"current_wave_time\":0.00,\"distance_travelled\":1.500}");
+    }
+
+    #[test]
+    fn to_json_times_the_wave_in_progress() {
+        let mut stats = RunStats::new();
+        stats.new_wave();
+        assert!(stats.wave_times.is_empty());
+        assert!(stats.to_json(1, 0).contains("\"current_wave_time\":0.0"));
+        stats.new_wave();
+        assert_eq!(stats.wave_times.len(), 1);
+    }
+}
@@ -0,0 +1,139 @@
+use cgmath::{vec3, Matrix4, Point3, Deg, InnerSpace};
+
+use crate::flying_camera::Flying_Camera;
+use crate::flying_camera::Flying_Camera_Movement::{UP, DOWN, LEFT, RIGHT};
+use crate::game::Render;
+use crate::gl;
+use crate::gl_helper::instance_model::ModelInstance;
+use crate::gl_helper::model::Normals;
+use crate::gl_helper::assets::Assets;
+use crate::gl_helper::shader::ShaderProgram;
+use crate::input::{Action, Input};
+
+const CHASE_DISTANCE: f32 = 0.3;
+const CHASE_HEIGHT: f32 = 0.12;
+const TOP_DOWN_HEIGHT: f32 = 5.0;
+const DEBUG_SPEED: f32 = 0.2;
+const SHIP_MODEL: &str = "resources/models/ship.obj";
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CameraMode {
+    FirstPerson,
+    Chase,
+    TopDown,
+    FreeDebug,
+}
+
+/// Where the scene is viewed from. The player is always the Flying_Camera held by the
+/// runtime, this only decides how to look at it.
+pub struct ViewCamera {
+    pub mode: CameraMode,
+    gameplay_mode: CameraMode,
+    debug: Flying_Camera,
+    ship: ModelInstance,
+}
+
+impl ViewCamera {
+    pub fn new(gl: &gl::Gl, assets: &mut Assets) -> ViewCamera {
+        let ship = assets.model(gl, SHIP_MODEL, Normals::Flat)
+            .unwrap_or_else(|e| panic!("unable to load the ship model {}", e));
+        ViewCamera {
+            mode: CameraMode::FirstPerson,
+            gameplay_mode: CameraMode::FirstPerson,
+            debug: Flying_Camera::default(),
+            ship: ModelInstance::new(ship, 1.0),
+        }
+    }
+
+    /// Steps through the views used for playing, the debug camera is toggled separately
+    pub fn next_mode(&mut self) {
+        self.gameplay_mode = match self.gameplay_mode {
+            CameraMode::FirstPerson => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::TopDown,
+            CameraMode::TopDown | CameraMode::FreeDebug => CameraMode::FirstPerson,
+        };
+        if self.mode != CameraMode::FreeDebug {
+            self.mode = self.gameplay_mode;
+        }
+    }
+
+    /// Switches to a free flying camera starting from where the player is, and back again
+    pub fn toggle_debug(&mut self, player: &Flying_Camera) {
+        if self.mode == CameraMode::FreeDebug {
+            self.mode = self.gameplay_mode;
+        } else {
+            self.debug = Flying_Camera {
+                Position: player.Position,
+                Yaw: player.Yaw,
+                Pitch: player.Pitch,
+                ..Flying_Camera::default()
+            };
+            self.debug.updateFlying_CameraVectors();
+            self.mode = CameraMode::FreeDebug;
+        }
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.mode == CameraMode::FreeDebug
+    }
+
+    /// Flies the debug camera with the player controls, the player itself stays where it is
+    pub fn update_debug(&mut self, input: &Input, delta: f32) {
+        if input.held(Action::PitchUp) { self.debug.processKeyboard(UP, delta); }
+        if input.held(Action::PitchDown) { self.debug.processKeyboard(DOWN, delta); }
+        if input.held(Action::TurnLeft) { self.debug.processKeyboard(LEFT, delta); }
+        if input.held(Action::TurnRight) { self.debug.processKeyboard(RIGHT, delta); }
+        let velocity = DEBUG_SPEED * delta;
+        let front = self.debug.Front;
+        let right = self.debug.Right;
+        let up = self.debug.WorldUp;
+        if input.held(Action::Forward) { self.debug.Position += front * velocity; }
+        if input.held(Action::Backward) { self.debug.Position -= front * velocity; }
+        if input.held(Action::StrafeRight) { self.debug.Position += right * velocity; }
+        if input.held(Action::StrafeLeft) { self.debug.Position -= right * velocity; }
+        if input.held(Action::Ascend) { self.debug.Position += up * velocity; }
+        if input.held(Action::Descend) { self.debug.Position -= up * velocity; }
+    }
+
+    pub fn mouse_look(&mut self, xoffset: f32, yoffset: f32, sensitivity: f32, invert_y: bool) {
+        self.debug.processMouseMovement(xoffset, yoffset, sensitivity, invert_y);
+    }
+
+    pub fn eye(&self, player: &Flying_Camera) -> Point3<f32> {
+        match self.mode {
+            CameraMode::FirstPerson => player.Position,
+            CameraMode::Chase => player.Position - player.direction * CHASE_DISTANCE + player.WorldUp * CHASE_HEIGHT,
+            CameraMode::TopDown => Point3::new(0.0, TOP_DOWN_HEIGHT, 0.0),
+            CameraMode::FreeDebug => self.debug.Position,
+        }
+    }
+
+    pub fn view_matrix(&self, player: &Flying_Camera) -> Matrix4<f32> {
+        match self.mode {
+            CameraMode::FirstPerson => player.GetViewMatrix(),
+            CameraMode::Chase => {
+                let target = player.Position + player.Front * CHASE_DISTANCE;
+                Matrix4::look_at(self.eye(player), target, player.WorldUp)
+            }
+            // looking straight down, so up on the screen is away from the start position like the arcade game
+            CameraMode::TopDown => Matrix4::look_at(self.eye(player), Point3::new(0.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0)),
+            CameraMode::FreeDebug => self.debug.GetViewMatrix(),
+        }
+    }
+
+    pub fn shader(&self) -> &ShaderProgram {
+        self.ship.shader()
+    }
+
+    /// Draws the player ship in every view except first person
+    pub fn render_player(&mut self, gl: &gl::Gl, player: &Flying_Camera) {
+        if self.mode == CameraMode::FirstPerson {
+            return;
+        }
+        let heading = Deg(-(player.Yaw + 90.0));
+        let below_eye = player.WorldUp.normalize() * 0.02;
+        self.ship.matrix = Matrix4::from_translation(vec3(player.Position.x, player.Position.y, player.Position.z) - below_eye) *
+            Matrix4::from_angle_y(heading);
+        self.ship.render(gl);
+    }
+}